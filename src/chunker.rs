@@ -0,0 +1,128 @@
+//! Groups a note's markdown AST into token-bounded chunks for embedding.
+//!
+//! `EmbeddingIndex::index` used to map each top-level AST child straight to
+//! one passage, which produces one-line fragments for list items and
+//! oversized blobs for long sections. This instead accumulates sibling
+//! blocks under an approximate token budget, so each chunk stays close to
+//! the embedding model's sweet spot regardless of how the source document
+//! happens to be structured.
+
+use markdown_it::{plugins::cmark::block::heading::ATXHeading, Node};
+
+/// Rough token budget per chunk, tuned for e5-large's context window.
+pub const CHUNK_TOKEN_BUDGET: usize = 256;
+
+/// Trailing sentences from the previous chunk repeated at the start of the
+/// next one, so an embedding near a chunk boundary still sees some of the
+/// surrounding context.
+const OVERLAP_SENTENCES: usize = 1;
+
+/// Estimate a text's token count from its whitespace-split word count.
+/// English text averages roughly 1.3 BPE tokens per word, which is good
+/// enough for budgeting a chunk without wiring in a real tokenizer.
+fn estimate_tokens(text: &str) -> usize {
+    let words = text.split_whitespace().count();
+    ((words as f32) * 1.3).ceil() as usize
+}
+
+/// The last `n` sentences of `text`, used as overlap at the start of the
+/// next chunk. Splits on `.`/`!`/`?`; falls back to the whole text if it
+/// has no sentence boundaries to split on.
+fn trailing_sentences(text: &str, n: usize) -> String {
+    let sentences: Vec<&str> = text
+        .split_inclusive(['.', '!', '?'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    sentences
+        .iter()
+        .rev()
+        .take(n)
+        .rev()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A chunk under construction: the nearest enclosing heading, the overlap
+/// carried over from the previous chunk, and the blocks accumulated so far.
+struct ChunkBuilder {
+    heading: String,
+    overlap: String,
+    body: String,
+}
+
+impl ChunkBuilder {
+    fn new(heading: &str, overlap: &str) -> Self {
+        Self {
+            heading: heading.to_owned(),
+            overlap: overlap.to_owned(),
+            body: String::new(),
+        }
+    }
+
+    fn token_count(&self) -> usize {
+        estimate_tokens(&self.heading) + estimate_tokens(&self.overlap) + estimate_tokens(&self.body)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.body.is_empty()
+    }
+
+    fn push(&mut self, text: &str) {
+        if !self.body.is_empty() {
+            self.body.push_str("\n\n");
+        }
+        self.body.push_str(text);
+    }
+
+    fn render(&self) -> String {
+        [self.heading.as_str(), self.overlap.as_str(), self.body.as_str()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Walk a note's markdown AST and group its top-level blocks into chunks
+/// bounded by `budget_tokens`. Headings aren't chunked on their own: the
+/// nearest enclosing heading text is prepended to every chunk under it, so
+/// headings give each chunk local context. A block that alone exceeds the
+/// budget (a long code fence or table) is never split across chunks; it
+/// becomes its own oversized chunk instead.
+pub fn chunk_note(markdown: &Node, budget_tokens: usize) -> Vec<(String, usize)> {
+    let mut chunks: Vec<String> = Vec::new();
+    let mut heading = String::new();
+    let mut overlap = String::new();
+    let mut current = ChunkBuilder::new(&heading, &overlap);
+
+    for child in &markdown.children {
+        if child.is::<ATXHeading>() {
+            if !current.is_empty() {
+                overlap = trailing_sentences(&current.body, OVERLAP_SENTENCES);
+                chunks.push(current.render());
+            }
+            heading = child.collect_text();
+            current = ChunkBuilder::new(&heading, &overlap);
+            continue;
+        }
+
+        let text = child.collect_text();
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        if !current.is_empty() && current.token_count() + estimate_tokens(&text) > budget_tokens {
+            overlap = trailing_sentences(&current.body, OVERLAP_SENTENCES);
+            chunks.push(current.render());
+            current = ChunkBuilder::new(&heading, &overlap);
+        }
+        current.push(&text);
+    }
+    if !current.is_empty() {
+        chunks.push(current.render());
+    }
+
+    chunks.into_iter().enumerate().map(|(i, text)| (text, i)).collect()
+}
@@ -0,0 +1,380 @@
+use anyhow::Result;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, RecvError, RecvTimeoutError},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::SqliteConnection;
+
+use crate::{
+    note::Note,
+    sqlite::{
+        embedding_index::{self, EmbeddingBatcher},
+        fts_index,
+        store::{FtsHit, IndexStore, SqliteStore},
+        ConnectionOptions, ReadPool, SqliteIndex, SqliteInitConfig, SQL_INDEX_NAME,
+    },
+    watcher::{self},
+};
+
+pub mod writer;
+use writer::{Inserter, WriteOp};
+
+pub trait IndexExt<'a> {
+    type InitCfg;
+    type NoteIn;
+    // Called to initialize the index if it doesn't exist yet.
+    fn init(&mut self, config: &Self::InitCfg);
+    // Called to add a note to the index.
+    fn index(&mut self, note: &Self::NoteIn);
+    // Called to remove a note from the index.
+    fn remove(&mut self, path: &Path);
+    // Called when a note is moved or renamed without its content changing,
+    // so that the new path can be written in place instead of reindexing.
+    fn rename(&mut self, from: &Path, to: &Path);
+}
+
+/// Which `IndexStore` implementation `Indexer::init` constructs. A
+/// single-variant enum today since `SqliteStore` is the only driver, but
+/// this (rather than `Indexer::init` hardcoding it) is the seam an
+/// alternative embedded engine plugs into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageBackend {
+    #[default]
+    Sqlite,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexerInitConfig {
+    pub vault_root_path: PathBuf,
+    pub index_dir: PathBuf,
+    /// Number of worker threads that parse notes and run extension logic in
+    /// parallel. Defaults to the number of available CPUs.
+    pub num_worker_threads: usize,
+    /// Number of threads the embedding batcher runs `passage_embed` calls
+    /// on. Kept separate (and smaller) than `num_worker_threads`, since
+    /// embedding throughput is batch-bound rather than scaling with however
+    /// many notes are being parsed concurrently.
+    pub num_embedding_threads: usize,
+    /// How long a connection waits on SQLite's lock before giving up.
+    pub busy_timeout_ms: u32,
+    /// Which `IndexStore` backs the index. Defaults to (and today, can
+    /// only be) `Sqlite`.
+    pub storage_backend: StorageBackend,
+}
+
+impl IndexerInitConfig {
+    pub fn new(vault_root_path: PathBuf, index_dir: PathBuf) -> Self {
+        Self {
+            vault_root_path,
+            index_dir,
+            num_worker_threads: default_num_worker_threads(),
+            num_embedding_threads: default_num_embedding_threads(),
+            busy_timeout_ms: 5_000,
+            storage_backend: StorageBackend::default(),
+        }
+    }
+}
+
+fn default_num_worker_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+fn default_num_embedding_threads() -> usize {
+    2
+}
+
+/// How long `start()` waits for a new event before flushing whatever chunks
+/// are sitting in the embedding batcher's queue. Without this, a trickle of
+/// edits that never accumulates a full `EMBEDDING_BATCH_SIZE` would leave
+/// those chunks queued (and so absent from `search_semantic`) for as long
+/// as the process kept running, since nothing but shutdown ever called
+/// `EmbeddingBatcher::flush` outside of tests.
+const EMBEDDING_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Producer/consumer indexing pipeline:
+///   watcher -> work queue -> worker pool (parse + extensions) -> write queue -> inserter
+///
+/// Each worker owns its own full extension tree (a `SqliteIndex` and its
+/// children) so that CPU-bound work like markdown parsing and embedding runs
+/// in parallel, while all of them funnel their writes through a single
+/// dedicated inserter thread, since SQLite only ever allows one writer.
+pub struct Indexer {
+    pub vault_root_path: PathBuf,
+    pub index_event_receiver: Receiver<watcher::IndexEvent>,
+    num_worker_threads: usize,
+    /// Count of dispatched-but-not-yet-durable events, notified on change.
+    pending: Arc<(Mutex<usize>, Condvar)>,
+    /// One bounded channel per worker rather than one shared queue: `dispatch`
+    /// hashes each event's path to a fixed worker, so a changed file's
+    /// `Remove` and `Add` (emitted as two independent events) always land on
+    /// the same worker and so reach the single inserter in that order,
+    /// instead of racing each other through two different workers.
+    work_senders: Vec<crossbeam_channel::Sender<watcher::IndexEvent>>,
+    worker_handles: Vec<thread::JoinHandle<()>>,
+    inserter_handle: Option<thread::JoinHandle<()>>,
+    /// Outlives the worker pool so its trailing partial batch can be
+    /// flushed once every worker has stopped enqueueing chunks.
+    embedding_batch: Option<Arc<EmbeddingBatcher>>,
+    /// Kept around (rather than only living inside each worker's extension
+    /// tree) so `search_fts`/`search_semantic` can read the index without
+    /// going through a worker at all.
+    store: Option<Arc<dyn IndexStore>>,
+}
+
+impl Indexer {
+    pub fn new(
+        vault_root_path: PathBuf,
+        index_event_receiver: Receiver<watcher::IndexEvent>,
+    ) -> Self {
+        Self {
+            vault_root_path,
+            index_event_receiver,
+            num_worker_threads: default_num_worker_threads(),
+            pending: Arc::new((Mutex::new(0), Condvar::new())),
+            work_senders: Vec::new(),
+            worker_handles: Vec::new(),
+            inserter_handle: None,
+            embedding_batch: None,
+            store: None,
+        }
+    }
+
+    pub fn init(&mut self, config: &IndexerInitConfig) {
+        log::info!("Indexing pipeline initializing.");
+        self.num_worker_threads = config.num_worker_threads.max(1);
+
+        let db_path = config.index_dir.join(SQL_INDEX_NAME);
+        let conn_options = ConnectionOptions {
+            busy_timeout_ms: config.busy_timeout_ms,
+        };
+
+        let mut writer_conn: SqliteConnection =
+            diesel::Connection::establish(db_path.to_str().unwrap()).unwrap();
+        conn_options.apply(&mut writer_conn).unwrap();
+        let conn = Arc::new(Mutex::new(writer_conn));
+
+        let read_pool: ReadPool = Pool::builder()
+            .connection_customizer(Box::new(conn_options))
+            .build(ConnectionManager::new(db_path.to_str().unwrap()))
+            .unwrap();
+
+        let (write_sender, write_receiver) = crossbeam_channel::unbounded::<WriteOp>();
+        self.inserter_handle = Some(thread::spawn({
+            let conn = Arc::clone(&conn);
+            move || Inserter::new(conn, write_receiver).run()
+        }));
+
+        let store: Arc<dyn IndexStore> = match config.storage_backend {
+            StorageBackend::Sqlite => Arc::new(SqliteStore::new(conn, write_sender.clone(), read_pool)),
+        };
+        self.store = Some(Arc::clone(&store));
+
+        let embedding_batch = Arc::new(EmbeddingBatcher::new(
+            &config.index_dir,
+            Arc::clone(&store),
+            config.num_embedding_threads.max(1),
+        ));
+        self.embedding_batch = Some(Arc::clone(&embedding_batch));
+
+        let sqlite_config = SqliteInitConfig {
+            vault_root_path: config.vault_root_path.clone(),
+            index_dir: config.index_dir.clone(),
+            db_path,
+            store,
+            embedding_batch,
+        };
+
+        // Bounded so a slow parse/embedding stage applies backpressure to
+        // the watcher instead of buffering the whole vault in memory. One
+        // channel per worker, not shared, so `dispatch` can pin a path to a
+        // worker instead of letting same-path events race through whichever
+        // worker happens to be free.
+        let mut work_senders = Vec::with_capacity(self.num_worker_threads);
+
+        for worker_id in 0..self.num_worker_threads {
+            let (work_sender, work_receiver) = crossbeam_channel::bounded::<watcher::IndexEvent>(4);
+            work_senders.push(work_sender);
+
+            let vault_root_path = self.vault_root_path.clone();
+            let write_sender = write_sender.clone();
+            let sqlite_config = sqlite_config.clone();
+            let pending = Arc::clone(&self.pending);
+            self.worker_handles.push(thread::spawn(move || {
+                let mut tree = SqliteIndex::new();
+                tree.init(&sqlite_config);
+                log::debug!("Indexer worker {worker_id} initialized.");
+                while let Ok(event) = work_receiver.recv() {
+                    handle_single_event(&mut tree, &vault_root_path, event);
+                    write_sender
+                        .send(WriteOp::Barrier(Arc::clone(&pending)))
+                        .ok();
+                }
+            }));
+        }
+
+        self.work_senders = work_senders;
+        log::info!(
+            "Indexing pipeline initialized with {} workers.",
+            self.num_worker_threads
+        );
+    }
+
+    /// Which worker owns `path`: every event for the same path is always
+    /// routed here, so a `Remove` followed by an `Add` for the same file
+    /// (the watcher's changed-file pattern) are handled by one worker in
+    /// the order they were dispatched, and so reach the inserter in that
+    /// order too.
+    fn worker_for(&self, path: &Path) -> usize {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        path.hash(&mut hasher);
+        (hasher.finish() as usize) % self.work_senders.len()
+    }
+
+    fn dispatch(&self, event: watcher::IndexEvent) {
+        *self.pending.0.lock().unwrap() += 1;
+        let worker = self.worker_for(event.path());
+        self.work_senders[worker].send(event).unwrap();
+    }
+
+    /// Block until every dispatched event has been fully committed by the
+    /// inserter thread, including any embedding chunks those events queued:
+    /// an event being durable only means its own extensions committed, not
+    /// that a since-queued-but-not-yet-batch-sized chunk made it in too, so
+    /// the embedding batcher is flushed and drained here as well. Without
+    /// this, `process()` could return before every embedding for the
+    /// just-indexed notes existed, breaking its documented guarantee that
+    /// the whole pipeline is drained by the time it returns.
+    fn wait_until_drained(&self) {
+        let mut count = self.pending.0.lock().unwrap();
+        while *count > 0 {
+            count = self.pending.1.wait(count).unwrap();
+        }
+        drop(count);
+
+        if let Some(embedding_batch) = &self.embedding_batch {
+            embedding_batch.flush();
+            embedding_batch.wait_idle();
+        }
+        if let Some(store) = &self.store {
+            store.flush();
+        }
+    }
+
+    /// Keyword search over indexed notes via FTS5.
+    pub fn search_fts(&self, query: &str, limit: usize) -> Vec<FtsHit> {
+        fts_index::search(self.store.as_ref().unwrap().as_ref(), query, limit)
+    }
+
+    /// Semantic search over indexed notes' chunk embeddings.
+    pub fn search_semantic(&self, query: &str, k: usize) -> Vec<(PathBuf, usize, f32)> {
+        embedding_index::search(
+            self.store.as_ref().unwrap().as_ref(),
+            self.embedding_batch.as_ref().unwrap(),
+            query,
+            k,
+        )
+    }
+
+    /// Handle index events in an infinite loop. Whenever `EMBEDDING_FLUSH_INTERVAL`
+    /// passes with no new event, the embedding batcher's queue is flushed so a
+    /// slow trickle of edits still lands in the semantic index within a
+    /// bounded window, rather than only once the pipeline shuts down.
+    /// If you only want to handle the current events, use `process` instead.
+    pub fn start(&mut self) {
+        loop {
+            match self
+                .index_event_receiver
+                .recv_timeout(EMBEDDING_FLUSH_INTERVAL)
+            {
+                Ok(event) => self.dispatch(event),
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(embedding_batch) = &self.embedding_batch {
+                        embedding_batch.flush();
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.wait_until_drained();
+    }
+
+    /// Used for testing.
+    /// Handle all remaining events until the queue is empty, then block
+    /// until the worker pool and inserter have fully drained, so tests
+    /// observe a consistent DB.
+    ///
+    /// If you want to handle events continuously, use `start` instead.
+    #[allow(dead_code)]
+    pub fn process(&mut self) -> Result<()> {
+        loop {
+            match self
+                .index_event_receiver
+                .recv_timeout(std::time::Duration::from_millis(100))
+            {
+                Ok(event) => self.dispatch(event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Err(RecvError.into()),
+            }
+        }
+        self.wait_until_drained();
+        Ok(())
+    }
+}
+
+impl Drop for Indexer {
+    fn drop(&mut self) {
+        // Dropping the work senders lets every worker's `recv` loop end; once
+        // all of their `WriteOp` senders are gone with them, the inserter
+        // sees its channel disconnect, flushes, and stops on its own.
+        self.work_senders.clear();
+        for handle in self.worker_handles.drain(..) {
+            handle.join().ok();
+        }
+        // Every worker has stopped enqueueing by now, so flushing the
+        // batcher and waiting for it to go idle here blocks until the
+        // embedding pool finishes the tail batch (and the `WriteOp` it
+        // sends), before the inserter is allowed to shut down.
+        if let Some(embedding_batch) = self.embedding_batch.take() {
+            embedding_batch.flush();
+            embedding_batch.wait_idle();
+            drop(embedding_batch);
+        }
+        // `store` holds a `WriteOp` sender of its own, so the inserter's
+        // channel only disconnects once it is dropped as well.
+        self.store = None;
+        if let Some(handle) = self.inserter_handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+fn handle_single_event(
+    tree: &mut SqliteIndex,
+    vault_root_path: &Path,
+    event: watcher::IndexEvent,
+) {
+    match event {
+        watcher::IndexEvent::Add(rel_path) => {
+            let note = Note::new(vault_root_path, &rel_path);
+            tree.index(&note);
+            log::info!("Indexed file: {:?}", rel_path);
+        }
+        watcher::IndexEvent::Remove(rel_path) => {
+            tree.remove(&rel_path);
+            log::info!("Removed file: {:?}", rel_path);
+        }
+        watcher::IndexEvent::Rename { from, to } => {
+            tree.rename(&from, &to);
+            log::info!("Renamed file: {:?} -> {:?}", from, to);
+        }
+    }
+}
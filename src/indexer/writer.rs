@@ -0,0 +1,108 @@
+//! The writer module owns the single SQLite connection used for all writes.
+//! Index extensions no longer write to the database themselves: their
+//! `IndexStore` (`SqliteStore`) builds a `WriteOp` describing the row(s) to
+//! persist and sends it here, so that the
+//! (possibly many) worker threads doing the CPU-bound parsing/embedding work
+//! never contend with each other over the connection.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use diesel::connection::Connection;
+use diesel::RunQueryDsl;
+use diesel::SqliteConnection;
+
+/// A unit of work for the dedicated writer thread.
+pub enum WriteOp {
+    /// Apply a single write (insert/delete) against the connection.
+    Exec(Box<dyn FnOnce(&mut SqliteConnection) + Send>),
+    /// Marks the end of one `IndexEvent`'s writes. By the time the inserter
+    /// reaches a barrier it has committed every `Exec` queued before it, so
+    /// it is safe to tell the dispatcher that this event is fully durable.
+    Barrier(Arc<(Mutex<usize>, Condvar)>),
+}
+
+impl WriteOp {
+    pub fn exec(f: impl FnOnce(&mut SqliteConnection) + Send + 'static) -> Self {
+        WriteOp::Exec(Box::new(f))
+    }
+}
+
+/// Commit even if more ops keep arriving, so a busy vault doesn't grow the
+/// buffer unboundedly.
+const MAX_BATCH: usize = 256;
+/// How long to wait for more ops before flushing a partial batch.
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Runs on its own thread and is the only thing that ever touches the
+/// connection after startup. Applies buffered `WriteOp`s in batched
+/// transactions instead of one transaction per row.
+pub struct Inserter {
+    conn: Arc<Mutex<SqliteConnection>>,
+    receiver: Receiver<WriteOp>,
+    buffer: Vec<Box<dyn FnOnce(&mut SqliteConnection) + Send>>,
+}
+
+impl Inserter {
+    pub fn new(conn: Arc<Mutex<SqliteConnection>>, receiver: Receiver<WriteOp>) -> Self {
+        Self {
+            conn,
+            receiver,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn run(mut self) {
+        loop {
+            match self.receiver.recv_timeout(FLUSH_TIMEOUT) {
+                Ok(WriteOp::Exec(op)) => {
+                    self.buffer.push(op);
+                    if self.buffer.len() >= MAX_BATCH {
+                        self.flush();
+                    }
+                }
+                Ok(WriteOp::Barrier(pending)) => {
+                    self.flush();
+                    let (lock, cvar) = &*pending;
+                    *lock.lock().unwrap() -= 1;
+                    cvar.notify_all();
+                }
+                Err(RecvTimeoutError::Timeout) => self.flush(),
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush();
+                    break;
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut conn = self.conn.lock().unwrap();
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            // `rename_*` ops UPDATE a parent key and its children in the
+            // same transaction; `PRAGMA foreign_keys = ON` checks FKs
+            // per-statement by default, so no ordering of those UPDATEs
+            // satisfies it mid-transaction. Deferring moves the checks to
+            // COMMIT, once every row in the batch points at a consistent
+            // set of keys again. SQLite resets this back off when the
+            // transaction ends, so it's safe to set unconditionally here.
+            diesel::sql_query("PRAGMA defer_foreign_keys = ON").execute(conn)?;
+            for op in self.buffer.drain(..) {
+                op(conn);
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+}
+
+impl Drop for Inserter {
+    /// Make sure a shutdown mid-batch doesn't lose buffered rows.
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -1,22 +1,22 @@
 use crate::{
     indexer::IndexExt,
     markdown_index::MarkdownNote,
-    sqlite::{models, schema, SqliteInitConfig},
+    sqlite::{
+        models,
+        store::{IndexStore, Table},
+        SqliteInitConfig,
+    },
     wikilink_parser::Wikilink,
 };
-use diesel::{ExpressionMethods, RunQueryDsl, SqliteConnection};
-use std::{
-    path::Path,
-    sync::{Arc, Mutex},
-};
+use std::{path::Path, sync::Arc};
 
 pub struct LinkIndex {
-    conn: Option<Arc<Mutex<SqliteConnection>>>,
+    store: Option<Arc<dyn IndexStore>>,
 }
 
 impl LinkIndex {
     pub fn new() -> Self {
-        Self { conn: None }
+        Self { store: None }
     }
 }
 
@@ -25,7 +25,12 @@ impl LinkIndex {
 fn link_url_to_rel_path(link_url: &str) -> String {
     let url_decoded = &*urlencoding::decode(link_url).unwrap();
     let without_prefix = url_decoded.trim_start_matches("./");
-    without_prefix.to_owned()
+    // Stored (and matched against in `rename_links`) the same way a
+    // wikilink target is, so a markdown link written with an explicit
+    // `.md` extension (e.g. `[text](note.md)`, common in Obsidian-style
+    // vaults) still resolves to the same vault path as `[[note]]` would.
+    let without_extension = without_prefix.strip_suffix(".md").unwrap_or(without_prefix);
+    without_extension.to_owned()
 }
 
 impl<'a> IndexExt<'a> for LinkIndex {
@@ -33,24 +38,8 @@ impl<'a> IndexExt<'a> for LinkIndex {
     type NoteIn = MarkdownNote<'a>;
 
     fn init(&mut self, config: &Self::InitCfg) {
-        self.conn = Some(Arc::clone(&config.conn));
-
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-        diesel::sql_query(
-            r#"
-                    CREATE TABLE IF NOT EXISTS link (
-                        "from" TEXT NOT NULL,
-                        "to" TEXT NOT NULL,
-                        "text" TEXT,
-                        "start" INTEGER,
-                        "end" INTEGER,
-                        PRIMARY KEY("from", "start"),
-                        FOREIGN KEY("from") REFERENCES note (file)
-                    )
-                "#,
-        )
-        .execute(&mut *conn)
-        .unwrap();
+        self.store = Some(Arc::clone(&config.store));
+        self.store.as_ref().unwrap().init_schema(Table::Link);
         log::info!("Index extension LinkIndex initialized.");
     }
 
@@ -62,10 +51,12 @@ impl<'a> IndexExt<'a> for LinkIndex {
             if node.is::<inline::link::Link>() {
                 let link = node.cast::<inline::link::Link>().unwrap();
                 let (start, end) = node.srcmap.unwrap().get_byte_offsets();
+                let text = node.collect_text();
                 links.push(models::Link {
                     from: md_note.note.rel_path.to_str().unwrap().to_owned(),
                     to: link_url_to_rel_path(&link.url),
-                    text: None, // TODO
+                    text: (!text.is_empty()).then_some(text),
+                    anchor: None,
                     start: start.try_into().unwrap(),
                     end: end.try_into().unwrap(),
                 });
@@ -76,27 +67,39 @@ impl<'a> IndexExt<'a> for LinkIndex {
                 links.push(models::Link {
                     from: md_note.note.rel_path.to_str().unwrap().to_owned(),
                     to: wikilink.target.to_owned(),
-                    text: None, // TODO
+                    text: wikilink.display.clone(),
+                    anchor: wikilink.anchor.clone(),
                     start: start.try_into().unwrap(),
                     end: end.try_into().unwrap(),
                 });
             }
         });
 
-        // Insert all links into the database.
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-        diesel::insert_into(schema::link::table)
-            .values(links)
-            .execute(&mut *conn)
-            .unwrap();
+        self.store.as_ref().unwrap().insert_links(links);
     }
 
     fn remove(&mut self, rel_path: &Path) {
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-        use schema::link::dsl::*;
-        diesel::delete(schema::link::table)
-            .filter(from.eq(rel_path.to_str().unwrap()))
-            .execute(&mut *conn)
-            .unwrap();
+        let rel_path_owned = rel_path.to_str().unwrap().to_owned();
+        self.store.as_ref().unwrap().delete_links(rel_path_owned);
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) {
+        let from_owned = from.to_str().unwrap().to_owned();
+        let to_owned = to.to_str().unwrap().to_owned();
+        // Links resolve their target by vault path (e.g. a wikilink target),
+        // not the note's relative file path, so `link.to` is rewritten in
+        // terms of the vault path rather than `to`/`from` directly.
+        let old_vault_path = crate::note::vault_path_from_relative_path(from)
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let new_vault_path = crate::note::vault_path_from_relative_path(to)
+            .to_str()
+            .unwrap()
+            .to_owned();
+        self.store
+            .as_ref()
+            .unwrap()
+            .rename_links(from_owned, to_owned, old_vault_path, new_vault_path);
     }
 }
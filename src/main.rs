@@ -8,11 +8,11 @@ use std::{
 };
 use watcher::{file_has_no_hidden_component, IndexEvent};
 
+mod chunker;
 mod indexer;
 pub mod note;
 mod sqlite;
 
-use crate::indexer::IndexExt;
 mod markdown_index;
 mod watcher;
 
@@ -98,10 +98,7 @@ fn main() {
     // the same file multiple times when there are frequent changes.
     let (index_event_sender, index_event_receiver) = channel::<watcher::IndexEvent>();
 
-    let config = IndexerInitConfig {
-        vault_root_path: PathBuf::from(&root_dir),
-        index_dir,
-    };
+    let config = IndexerInitConfig::new(PathBuf::from(&root_dir), index_dir);
     let indexer_task = indexer_start(&config, index_event_receiver);
 
     let watcher_task = watcher_start(
@@ -128,11 +125,11 @@ mod tests {
     use fastembed::{EmbeddingModel, FlagEmbedding, InitOptions};
 
     use crate::{
-        indexer::{IndexExt, IndexerInitConfig},
+        indexer::IndexerInitConfig,
         indexer_create,
         sqlite::{
             embedding_index::{EMBEDDING_MODEL_DIR, EMBEDDING_MODEL_NAME},
-            models::Link,
+            models::{Link, Tag},
             SQL_INDEX_NAME,
         },
         watcher::{self},
@@ -186,10 +183,7 @@ mod tests {
 
         let (index_event_sender, index_event_receiver) = channel::<watcher::IndexEvent>();
 
-        let config = IndexerInitConfig {
-            index_dir: index_dir.to_owned(),
-            vault_root_path: temp_dir.path().to_owned(),
-        };
+        let config = IndexerInitConfig::new(temp_dir.path().to_owned(), index_dir.to_owned());
 
         let watcher_task = {
             let index_dir = index_dir.clone();
@@ -272,4 +266,220 @@ mod tests {
 
         Ok(())
     }
+
+    /// Regression test for a `FOREIGN KEY` constraint panic: a rename's
+    /// `file`/`note`/`link`/`tag`/`embedding` UPDATEs land in one batched
+    /// transaction, and with `PRAGMA foreign_keys = ON` checking per
+    /// statement, no ordering of those UPDATEs satisfies it on its own --
+    /// the `Inserter` must defer FK checks to COMMIT for the batch.
+    #[test]
+    fn test_rename() -> Result<()> {
+        download_embedding_model();
+
+        let temp_dir = tempfile::tempdir()?;
+        let index_dir = Path::new(temp_dir.path()).join(INDEX_DIR_NAME);
+        std::fs::create_dir(&index_dir)?;
+
+        let model_target_dir = index_dir.join(EMBEDDING_MODEL_DIR);
+        std::fs::create_dir(&model_target_dir)?;
+        let model_source_path = model_directory().join(EMBEDDING_MODEL_NAME);
+        let model_target_path = model_target_dir.join(EMBEDDING_MODEL_NAME);
+        std::os::unix::fs::symlink(model_source_path, model_target_path)?;
+
+        let mut file1 = File::create(temp_dir.path().join("file1.md"))?;
+        let mut file2 = File::create(temp_dir.path().join("file2.md"))?;
+        write!(file1, "Hello File1, a [link](file2).")?;
+        write!(file2, "Hello File2, another [[file1]] back.")?;
+
+        let (index_event_sender, index_event_receiver) = channel::<watcher::IndexEvent>();
+        let config = IndexerInitConfig::new(temp_dir.path().to_owned(), index_dir.to_owned());
+
+        let watcher_task = {
+            let index_dir = index_dir.clone();
+            watcher_start(
+                &config,
+                index_event_sender,
+                move |path| !path.starts_with(&index_dir),
+                Some(std::time::Duration::from_millis(5000)),
+            )
+        };
+        let mut indexer = indexer_create(temp_dir.path().to_owned(), index_event_receiver);
+        indexer.init(&config);
+        indexer.process()?;
+
+        let mut conn: SqliteConnection =
+            Connection::establish(index_dir.join(SQL_INDEX_NAME).to_str().unwrap()).unwrap();
+
+        // Rename file1.md -> renamed.md. Without deferred FK checks, the
+        // inserter thread panics partway through the batch and the
+        // process() call below never returns.
+        std::fs::rename(
+            temp_dir.path().join("file1.md"),
+            temp_dir.path().join("renamed.md"),
+        )?;
+        indexer.process()?;
+
+        {
+            use crate::sqlite::schema::file::dsl::*;
+            use diesel::RunQueryDsl;
+            let paths: Vec<String> = file.select(path).load(&mut conn).unwrap();
+            assert!(paths.contains(&"renamed.md".to_string()));
+            assert!(!paths.contains(&"file1.md".to_string()));
+        }
+        {
+            use crate::sqlite::schema::link::dsl::*;
+            use diesel::RunQueryDsl;
+            let links = link.load::<Link>(&mut conn).unwrap();
+            // file2's wikilink to file1 gets rewritten to point at the new
+            // vault path, and the wikilink's own `from` is unaffected since
+            // it lives on file2.
+            assert!(links.iter().any(|l| l.from == "file2.md" && l.to == "renamed"));
+        }
+
+        drop(indexer.index_event_receiver);
+        watcher_task
+            .join()
+            .map_err(|_| anyhow!("Watcher thread panicked."))?;
+
+        temp_dir.close()?;
+        Ok(())
+    }
+
+    /// Covers `Indexer::search_fts`/`search_semantic`, which otherwise have
+    /// no caller now that `FtsIndex`/`EmbeddingIndex` no longer expose
+    /// search as an instance method on a buried `IndexExt` trait object.
+    #[test]
+    fn test_search() -> Result<()> {
+        // Download the embedding model if it doesn't exist yet.
+        download_embedding_model();
+
+        let temp_dir = tempfile::tempdir()?;
+        log::info!("Temp dir: {:?}", temp_dir.path());
+        let index_dir = Path::new(temp_dir.path()).join(INDEX_DIR_NAME);
+        std::fs::create_dir(&index_dir)?;
+
+        // Link the embedding model to the temp directory.
+        let model_target_dir = index_dir.join(EMBEDDING_MODEL_DIR);
+        std::fs::create_dir(&model_target_dir)?;
+        let model_source_path = model_directory().join(EMBEDDING_MODEL_NAME);
+        let model_target_path = model_target_dir.join(EMBEDDING_MODEL_NAME);
+        std::os::unix::fs::symlink(model_source_path, model_target_path)?;
+
+        let mut file1 = File::create(temp_dir.path().join("file1.md"))?;
+        write!(
+            file1,
+            "# Sourdough starter\n\nFeed the starter with flour and water every day."
+        )?;
+        let mut file2 = File::create(temp_dir.path().join("file2.md"))?;
+        write!(file2, "# Grocery list\n\nEggs, milk, bread.")?;
+
+        let (index_event_sender, index_event_receiver) = channel::<watcher::IndexEvent>();
+        let config = IndexerInitConfig::new(temp_dir.path().to_owned(), index_dir.to_owned());
+
+        let watcher_task = {
+            let index_dir = index_dir.clone();
+            watcher_start(
+                &config,
+                index_event_sender,
+                move |path| !path.starts_with(&index_dir),
+                Some(std::time::Duration::from_millis(5000)),
+            )
+        };
+        let mut indexer = indexer_create(temp_dir.path().to_owned(), index_event_receiver);
+        indexer.init(&config);
+        indexer.process()?;
+
+        let fts_hits = indexer.search_fts("sourdough", 5);
+        assert!(fts_hits.iter().any(|hit| hit.rel_path == "file1.md"));
+
+        let semantic_hits = indexer.search_semantic("feeding a bread starter", 5);
+        assert!(!semantic_hits.is_empty());
+        assert_eq!(semantic_hits[0].0, PathBuf::from("file1.md"));
+
+        drop(indexer.index_event_receiver);
+        watcher_task
+            .join()
+            .map_err(|_| anyhow!("Watcher thread panicked."))?;
+
+        temp_dir.close()?;
+        Ok(())
+    }
+
+    /// Covers `TagIndex`: hierarchical tags are indexed on note content, and
+    /// a tag's `note` foreign key follows a note rename, mirroring
+    /// `test_rename`'s coverage of `LinkIndex`.
+    #[test]
+    fn test_tag_rename() -> Result<()> {
+        download_embedding_model();
+
+        let temp_dir = tempfile::tempdir()?;
+        let index_dir = Path::new(temp_dir.path()).join(INDEX_DIR_NAME);
+        std::fs::create_dir(&index_dir)?;
+
+        let model_target_dir = index_dir.join(EMBEDDING_MODEL_DIR);
+        std::fs::create_dir(&model_target_dir)?;
+        let model_source_path = model_directory().join(EMBEDDING_MODEL_NAME);
+        let model_target_path = model_target_dir.join(EMBEDDING_MODEL_NAME);
+        std::os::unix::fs::symlink(model_source_path, model_target_path)?;
+
+        let mut file1 = File::create(temp_dir.path().join("file1.md"))?;
+        write!(file1, "Working on #project and #area/project today.")?;
+
+        let (index_event_sender, index_event_receiver) = channel::<watcher::IndexEvent>();
+        let config = IndexerInitConfig::new(temp_dir.path().to_owned(), index_dir.to_owned());
+
+        let watcher_task = {
+            let index_dir = index_dir.clone();
+            watcher_start(
+                &config,
+                index_event_sender,
+                move |path| !path.starts_with(&index_dir),
+                Some(std::time::Duration::from_millis(5000)),
+            )
+        };
+        let mut indexer = indexer_create(temp_dir.path().to_owned(), index_event_receiver);
+        indexer.init(&config);
+        indexer.process()?;
+
+        let mut conn: SqliteConnection =
+            Connection::establish(index_dir.join(SQL_INDEX_NAME).to_str().unwrap()).unwrap();
+
+        {
+            use crate::sqlite::schema::tag::dsl::*;
+            use diesel::RunQueryDsl;
+
+            let tags = tag.load::<Tag>(&mut conn).unwrap();
+            assert_eq!(tags.len(), 2);
+            assert!(tags
+                .iter()
+                .any(|t| t.note == "file1.md" && t.name == "project"));
+            assert!(tags
+                .iter()
+                .any(|t| t.note == "file1.md" && t.name == "area/project"));
+        }
+
+        // Rename file1.md -> renamed.md and check that its tags followed.
+        std::fs::rename(
+            temp_dir.path().join("file1.md"),
+            temp_dir.path().join("renamed.md"),
+        )?;
+        indexer.process()?;
+
+        {
+            use crate::sqlite::schema::tag::dsl::*;
+            use diesel::RunQueryDsl;
+
+            let tags = tag.load::<Tag>(&mut conn).unwrap();
+            assert_eq!(tags.len(), 2);
+            assert!(tags.iter().all(|t| t.note == "renamed.md"));
+        }
+
+        drop(indexer.index_event_receiver);
+        watcher_task
+            .join()
+            .map_err(|_| anyhow!("Watcher thread panicked."))?;
+
+        temp_dir.close()?;
+        Ok(())
+    }
 }
@@ -1,5 +1,6 @@
 use crate::{
-    indexer::IndexExt, link_index::LinkIndex, note, sqlite::SqliteInitConfig, wikilink_parser,
+    indexer::IndexExt, link_index::LinkIndex, note, sqlite::SqliteInitConfig, tag_index::TagIndex,
+    tag_parser, wikilink_parser,
 };
 use markdown_it::Node;
 use std::path::Path;
@@ -16,10 +17,15 @@ impl MarkdownIndex {
         markdown_it::plugins::cmark::add(&mut parser);
         markdown_it::plugins::extra::add(&mut parser);
         wikilink_parser::add(&mut parser);
+        tag_parser::add(&mut parser);
 
         Self {
             parser,
-            child_extensions: vec![Box::new(LinkIndex::new()), Box::new(EmbeddingIndex::new())],
+            child_extensions: vec![
+                Box::new(LinkIndex::new()),
+                Box::new(EmbeddingIndex::new()),
+                Box::new(TagIndex::new()),
+            ],
         }
     }
 }
@@ -64,4 +70,10 @@ impl IndexExt<'_> for MarkdownIndex {
             .iter_mut()
             .for_each(|ext| ext.remove(rel_path));
     }
+
+    fn rename(&mut self, from: &Path, to: &Path) {
+        self.child_extensions
+            .iter_mut()
+            .for_each(|ext| ext.rename(from, to));
+    }
 }
@@ -21,7 +21,9 @@ impl Note {
     }
 }
 
-fn vault_path_from_relative_path(rel_path: &Path) -> PathBuf {
+/// Vault path a wikilink would resolve to for a given note path, used to
+/// recognize `link.to` rows that target a note by its vault path.
+pub(crate) fn vault_path_from_relative_path(rel_path: &Path) -> PathBuf {
     match rel_path.extension() {
         // with_extension("") removes the extension
         Some(ext) if ext == "md" => rel_path.with_extension(""),
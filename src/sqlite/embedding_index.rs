@@ -1,25 +1,264 @@
-use std::path::Path;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
 
 use fastembed::{EmbeddingBase, EmbeddingModel, FlagEmbedding, InitOptions};
 
-use crate::{indexer::IndexExt, markdown_index::MarkdownNote};
+use crate::{
+    chunker::{self, CHUNK_TOKEN_BUDGET},
+    indexer::IndexExt,
+    markdown_index::MarkdownNote,
+};
 
-use super::SqliteInitConfig;
+use super::store::{IndexStore, Table};
+use super::{models, SqliteInitConfig};
+
+pub const EMBEDDING_MODEL_DIR: &str = "embedding_models";
+
+#[cfg(test)]
+pub const EMBEDDING_MODEL_NAME: &str = "fast-multilingual-e5-large";
+
+/// Number of chunks, accumulated from across however many notes are in
+/// flight, embedded together in a single `passage_embed` call.
+const EMBEDDING_BATCH_SIZE: usize = 64;
+
+struct PendingChunk {
+    rel_path: String,
+    chunk_index: i32,
+    chunk_text: String,
+}
+
+/// Owns the embedding model and batches chunks from across the whole worker
+/// pool before embedding them, instead of each worker embedding one note at
+/// a time as it finishes parsing it. Shared via `Arc` through
+/// `SqliteInitConfig` so the (expensive to load) model exists once for the
+/// whole pipeline rather than once per worker.
+pub struct EmbeddingBatcher {
+    /// Not behind a `Mutex`: `FlagEmbedding::passage_embed`/`query_embed`
+    /// take `&self`, since the underlying ONNX Runtime session supports
+    /// concurrent `run()` calls from multiple threads. Locking it here
+    /// would serialize every batch onto one thread regardless of `pool`'s
+    /// size, defeating the point of spawning them onto a worker pool.
+    model: FlagEmbedding,
+    pending: Mutex<Vec<PendingChunk>>,
+    store: Arc<dyn IndexStore>,
+    /// Bounded pool the `passage_embed` calls actually run on, separate
+    /// from the indexing worker pool, so embedding throughput isn't tied
+    /// to however many workers happen to be parsing notes.
+    pool: rayon::ThreadPool,
+    /// Count of batches spawned but not yet embedded and handed to the
+    /// store, notified on change. Lets `wait_idle` block until every batch
+    /// in flight has actually landed, the same way `Indexer::pending` lets
+    /// `wait_until_drained` block on dispatched-but-not-yet-durable events.
+    in_flight: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl EmbeddingBatcher {
+    pub fn new(index_dir: &Path, store: Arc<dyn IndexStore>, num_threads: usize) -> Self {
+        let model = FlagEmbedding::try_new(InitOptions {
+            model_name: EmbeddingModel::MLE5Large,
+            show_download_message: true,
+            cache_dir: index_dir.join(EMBEDDING_MODEL_DIR),
+            ..Default::default()
+        })
+        .unwrap();
+        log::info!("Embedding model initialized");
+
+        Self {
+            model,
+            pending: Mutex::new(Vec::new()),
+            store,
+            pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap(),
+            in_flight: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    /// Embed `query` against the same model used for stored chunks.
+    pub fn embed_query(&self, query: &str) -> Vec<f32> {
+        self.model.query_embed(query).unwrap()
+    }
+
+    /// Queue `rel_path`'s chunks for embedding, flushing a batch once
+    /// enough chunks have accumulated across notes.
+    pub fn enqueue(self: &Arc<Self>, rel_path: &str, chunks: Vec<(String, usize)>) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.extend(chunks.into_iter().map(|(chunk_text, chunk_index)| {
+            PendingChunk {
+                rel_path: rel_path.to_owned(),
+                chunk_index: chunk_index.try_into().unwrap(),
+                chunk_text,
+            }
+        }));
+        while pending.len() >= EMBEDDING_BATCH_SIZE {
+            let batch: Vec<PendingChunk> = pending.drain(..EMBEDDING_BATCH_SIZE).collect();
+            self.spawn_batch(batch);
+        }
+    }
+
+    /// Embed and insert whatever is left in the queue. Called once the
+    /// worker pool has shut down, so a tail smaller than one full batch
+    /// still makes it into the index instead of waiting forever for enough
+    /// siblings to arrive, and also from `Indexer::process`/`wait_until_drained`
+    /// so tests observe a consistent DB even when no worker ever filled a
+    /// full batch.
+    pub fn flush(self: &Arc<Self>) {
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if !batch.is_empty() {
+            self.spawn_batch(batch);
+        }
+    }
+
+    /// Block until every batch spawned so far (including one just flushed)
+    /// has finished embedding and handed its `insert_embeddings` write to
+    /// the store. Used by `Indexer::wait_until_drained` so `process()`
+    /// can't return while a batch is still mid-flight on the rayon pool.
+    pub fn wait_idle(&self) {
+        let (lock, cvar) = &*self.in_flight;
+        let mut count = lock.lock().unwrap();
+        while *count > 0 {
+            count = cvar.wait(count).unwrap();
+        }
+    }
+
+    fn spawn_batch(self: &Arc<Self>, batch: Vec<PendingChunk>) {
+        *self.in_flight.0.lock().unwrap() += 1;
+        let this = Arc::clone(self);
+        self.pool.spawn(move || {
+            let passages: Vec<String> = batch.iter().map(|c| c.chunk_text.clone()).collect();
+            let embeddings = this.model.passage_embed(passages, None).unwrap();
+
+            // One batch becomes one bulk insert, so the inserter commits it
+            // as a single write instead of one per chunk.
+            let rows: Vec<models::Embedding> = batch
+                .into_iter()
+                .zip(embeddings)
+                .map(|(chunk, vector)| models::Embedding {
+                    rel_path: chunk.rel_path,
+                    chunk_index: chunk.chunk_index,
+                    chunk_text: chunk.chunk_text,
+                    norm: l2_norm(&vector),
+                    vector: serialize_vector(&vector),
+                })
+                .collect();
+
+            this.store.insert_embeddings(rows);
+
+            let (lock, cvar) = &*this.in_flight;
+            *lock.lock().unwrap() -= 1;
+            cvar.notify_all();
+        });
+    }
+}
+
+/// Embed `query` and return the `k` stored chunks whose embeddings are
+/// closest to it by cosine similarity, descending by score.
+///
+/// Brute force over every stored chunk: fine for a personal vault, where
+/// the row count is small enough that a bounded min-heap of size `k` beats
+/// the bookkeeping of an index structure. A free function rather than a
+/// method on `EmbeddingIndex`, since the index's own instances live behind
+/// `IndexExt` trait objects inside each worker's tree and aren't reachable
+/// from outside it; callers that only need to search (such as
+/// `Indexer::search_semantic`) go through the shared
+/// `IndexStore`/`EmbeddingBatcher` directly.
+pub fn search(
+    store: &dyn IndexStore,
+    batcher: &EmbeddingBatcher,
+    query: &str,
+    k: usize,
+) -> Vec<(PathBuf, usize, f32)> {
+    let query_vector = batcher.embed_query(query);
+    let query_norm = l2_norm(&query_vector);
+
+    let rows = store.all_embeddings();
+
+    // Min-heap of the best `k` candidates seen so far: `Reverse` flips
+    // `BinaryHeap`'s usual max-heap ordering, so the weakest candidate is
+    // always the one on top and the one evicted once we're over size.
+    let mut heap: BinaryHeap<Reverse<ScoredChunk>> = BinaryHeap::with_capacity(k + 1);
+    for row in rows {
+        let vector = deserialize_vector(&row.vector);
+        let score = cosine_similarity(&query_vector, query_norm, &vector, row.norm);
+        heap.push(Reverse(ScoredChunk {
+            score,
+            rel_path: row.rel_path,
+            chunk_index: row.chunk_index,
+        }));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    let mut results: Vec<ScoredChunk> = heap.into_iter().map(|Reverse(c)| c).collect();
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results
+        .into_iter()
+        .map(|c| (PathBuf::from(c.rel_path), c.chunk_index as usize, c.score))
+        .collect()
+}
 
 pub struct EmbeddingIndex {
-    model: Option<FlagEmbedding>,
+    batcher: Option<Arc<EmbeddingBatcher>>,
+    store: Option<Arc<dyn IndexStore>>,
 }
 
 impl EmbeddingIndex {
     pub fn new() -> Self {
-        Self { model: None }
+        Self {
+            batcher: None,
+            store: None,
+        }
     }
 }
 
-pub const EMBEDDING_MODEL_DIR: &str = "embedding_models";
+/// A single scored chunk, ordered by `score` so it can live in a `BinaryHeap`.
+#[derive(Debug, PartialEq)]
+struct ScoredChunk {
+    rel_path: String,
+    chunk_index: i32,
+    score: f32,
+}
 
-#[cfg(test)]
-pub const EMBEDDING_MODEL_NAME: &str = "fast-multilingual-e5-large";
+impl Eq for ScoredChunk {}
+
+impl PartialOrd for ScoredChunk {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredChunk {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score.total_cmp(&other.score)
+    }
+}
+
+fn l2_norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+fn cosine_similarity(a: &[f32], a_norm: f32, b: &[f32], b_norm: f32) -> f32 {
+    if a_norm == 0.0 || b_norm == 0.0 {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    dot / (a_norm * b_norm)
+}
+
+fn serialize_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+fn deserialize_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
 
 impl<'a> IndexExt<'a> for EmbeddingIndex {
     type InitCfg = SqliteInitConfig;
@@ -27,41 +266,41 @@ impl<'a> IndexExt<'a> for EmbeddingIndex {
 
     fn init(&mut self, config: &Self::InitCfg) {
         log::info!("Initializing embedding index");
-        let model: FlagEmbedding = FlagEmbedding::try_new(InitOptions {
-            model_name: EmbeddingModel::MLE5Large,
-            show_download_message: true,
-            cache_dir: config.index_dir.join(EMBEDDING_MODEL_DIR),
-            ..Default::default()
-        })
-        .unwrap();
-        self.model = Some(model);
-        log::info!("Embedding model initialized");
+        self.batcher = Some(Arc::clone(&config.embedding_batch));
+        self.store = Some(Arc::clone(&config.store));
+
+        self.store.as_ref().unwrap().init_schema(Table::Embedding);
+
         log::info!("Index extension EmbeddingIndex initialized.");
     }
 
     fn index(&mut self, new_note: &MarkdownNote<'a>) {
-        // Get the sentences from the markdown AST.
-        let passages: Vec<String> = new_note
-            .markdown
-            .children
-            .iter()
-            .map(|child| child.collect_text())
-            .collect();
-        println!("{:?}", passages);
-
-        // Get the embeddings for each sentence.
-        let embeddings = self
-            .model
-            .as_mut()
-            .unwrap()
-            .passage_embed(passages, None)
-            .unwrap();
-        println!("{:?}", embeddings);
+        // Group the markdown AST into token-bounded, heading-aware chunks
+        // and queue them; `EmbeddingBatcher` decides when there are enough
+        // chunks (from this note and whichever others are in flight) to
+        // actually call `passage_embed`.
+        let chunks = chunker::chunk_note(&new_note.markdown, CHUNK_TOKEN_BUDGET);
+        if chunks.is_empty() {
+            return;
+        }
+        let rel_path = new_note.note.rel_path.to_str().unwrap().to_owned();
+        self.batcher.as_ref().unwrap().enqueue(&rel_path, chunks);
+    }
 
-        // TODO
+    fn remove(&mut self, rel_path: &Path) {
+        let rel_path_owned = rel_path.to_str().unwrap().to_owned();
+        self.store
+            .as_ref()
+            .unwrap()
+            .delete_embeddings(rel_path_owned);
     }
 
-    fn remove(&mut self, _rel_path: &Path) {
-        // TODO
+    fn rename(&mut self, from: &Path, to: &Path) {
+        let from_owned = from.to_str().unwrap().to_owned();
+        let to_owned = to.to_str().unwrap().to_owned();
+        self.store
+            .as_ref()
+            .unwrap()
+            .rename_embeddings(from_owned, to_owned);
     }
 }
@@ -0,0 +1,76 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::indexer::IndexExt;
+use crate::note;
+
+pub use super::store::FtsHit;
+use super::store::{IndexStore, Table};
+use super::SqliteInitConfig;
+
+/// Rank `query` against the indexed title/body using FTS5's `bm25()`
+/// scoring and return the top `limit` hits, best match first. A free
+/// function rather than a method on `FtsIndex`, since the index's own
+/// instances live behind `IndexExt` trait objects inside each worker's
+/// tree and aren't reachable from outside it; callers that only need to
+/// search (such as `Indexer::search_fts`) go through the shared
+/// `IndexStore` directly.
+pub fn search(store: &dyn IndexStore, query: &str, limit: usize) -> Vec<FtsHit> {
+    store.search_fts(query, limit)
+}
+
+/// Keyword search over indexed notes, complementing `EmbeddingIndex`'s
+/// semantic search with exact-term retrieval. Kept as a separate `IndexExt`
+/// child of `SqliteIndex` rather than folded into `NoteIndex`, the same way
+/// `MarkdownIndex`'s children each own one concern.
+pub struct FtsIndex {
+    store: Option<Arc<dyn IndexStore>>,
+}
+
+impl FtsIndex {
+    pub fn new() -> Self {
+        Self { store: None }
+    }
+}
+
+impl IndexExt<'_> for FtsIndex {
+    type InitCfg = SqliteInitConfig;
+    type NoteIn = note::Note;
+
+    fn init(&mut self, config: &Self::InitCfg) {
+        self.store = Some(Arc::clone(&config.store));
+        self.store.as_ref().unwrap().init_schema(Table::Fts);
+
+        log::info!("Index extension FtsIndex initialized.");
+    }
+
+    fn index(&mut self, new_note: &note::Note) {
+        let title = new_note
+            .rel_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_owned();
+        let body = std::fs::read_to_string(&new_note.absolute_path).unwrap_or_default();
+        let rel_path = new_note.rel_path.to_str().unwrap().to_owned();
+
+        self.store
+            .as_ref()
+            .unwrap()
+            .insert_fts(rel_path, title, body);
+    }
+
+    fn remove(&mut self, rel_path: &Path) {
+        let rel_path_owned = rel_path.to_str().unwrap().to_owned();
+        self.store.as_ref().unwrap().delete_fts(rel_path_owned);
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) {
+        let from_owned = from.to_str().unwrap().to_owned();
+        let to_owned = to.to_str().unwrap().to_owned();
+        self.store
+            .as_ref()
+            .unwrap()
+            .rename_fts(from_owned, to_owned);
+    }
+}
@@ -1,32 +1,112 @@
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool};
 use diesel::RunQueryDsl;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::time::UNIX_EPOCH;
 
-use crate::indexer::{IndexExt, IndexerInitConfig};
+use crate::indexer::IndexExt;
 use crate::note;
-use diesel::connection::Connection;
 use diesel::prelude::*;
 
 pub mod embedding_index;
+pub mod fts_index;
 pub mod models;
 pub mod note_index;
 pub mod schema;
+pub mod store;
+
+use store::{IndexStore, Table};
 
 pub const SQL_INDEX_NAME: &str = "index.db";
 
+pub type ReadPool = Pool<ConnectionManager<SqliteConnection>>;
+
+/// Pragmas applied to every connection we open, so that the `FOREIGN KEY`
+/// constraints declared in the schema are actually enforced, concurrent
+/// readers don't immediately collide with the writer, and commits don't wait
+/// on a full disk sync.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionOptions {
+    pub busy_timeout_ms: u32,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            busy_timeout_ms: 5_000,
+        }
+    }
+}
+
+impl ConnectionOptions {
+    pub fn apply(&self, conn: &mut SqliteConnection) -> QueryResult<()> {
+        diesel::sql_query("PRAGMA foreign_keys = ON").execute(conn)?;
+        diesel::sql_query(format!("PRAGMA busy_timeout = {}", self.busy_timeout_ms))
+            .execute(conn)?;
+        diesel::sql_query("PRAGMA synchronous = NORMAL").execute(conn)?;
+        diesel::sql_query("PRAGMA journal_mode = WAL").execute(conn)?;
+        Ok(())
+    }
+}
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        self.apply(conn)
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// Open a short-lived connection to the index database and run `f` against
+/// it. Used by callers (like the watcher's startup reconciliation scan) that
+/// only need to read the index occasionally and don't otherwise hold a
+/// connection of their own.
+pub fn with_db_conn<T>(db_path: &Path, f: impl FnOnce(&mut SqliteConnection) -> T) -> T {
+    let mut conn = diesel::Connection::establish(db_path.to_str().unwrap()).unwrap();
+    ConnectionOptions::default().apply(&mut conn).unwrap();
+    f(&mut conn)
+}
+
+/// Modification time of a file as a Unix timestamp, used as a cheap
+/// pre-filter before hashing its contents. Returns `0` if the file vanished
+/// or its metadata can't be read, so such a file always looks "changed".
+fn file_mtime_secs(path: &Path) -> i32 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| {
+            modified
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i32)
+                .unwrap_or(0)
+        })
+        .unwrap_or(0)
+}
+
+/// BLAKE3 hex digest of a file's contents.
+fn file_content_hash(path: &Path) -> String {
+    std::fs::read(path)
+        .map(|bytes| blake3::hash(&bytes).to_hex().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Clone)]
 pub struct SqliteInitConfig {
     pub vault_root_path: PathBuf,
     pub index_dir: PathBuf,
     pub db_path: PathBuf,
-    pub conn: Arc<Mutex<SqliteConnection>>,
+    /// Backing storage for the whole extension tree. Extensions talk to
+    /// this instead of `diesel`/`SqliteConnection` directly, so a different
+    /// engine can be selected here without changing any of them.
+    pub store: Arc<dyn IndexStore>,
+    /// Shared across every worker's `EmbeddingIndex`, so chunks from notes
+    /// parsed on different workers are batched into one `passage_embed`
+    /// call instead of embedding one note at a time. Built once for the
+    /// whole pipeline rather than once per worker, since loading the model
+    /// itself is the expensive part.
+    pub embedding_batch: Arc<embedding_index::EmbeddingBatcher>,
 }
 
 pub struct SqliteIndex {
-    // Use an Arc<Mutex<SqliteConnection>> instead of a RefCell<SqliteConnection>
-    // in case we want to handle multiple index events in parallel.
-    pub conn: Option<Arc<Mutex<SqliteConnection>>>,
+    pub store: Option<Arc<dyn IndexStore>>,
     pub child_extensions:
         Vec<Box<dyn for<'a> IndexExt<'a, InitCfg = SqliteInitConfig, NoteIn = note::Note>>>,
 }
@@ -34,59 +114,33 @@ pub struct SqliteIndex {
 impl SqliteIndex {
     pub fn new() -> Self {
         Self {
-            conn: None,
-            child_extensions: vec![Box::new(note_index::NoteIndex::new())],
+            store: None,
+            child_extensions: vec![
+                Box::new(note_index::NoteIndex::new()),
+                Box::new(fts_index::FtsIndex::new()),
+            ],
         }
     }
 }
 
 impl IndexExt<'_> for SqliteIndex {
-    type InitCfg = IndexerInitConfig;
+    type InitCfg = SqliteInitConfig;
     type NoteIn = note::Note;
 
     fn init(&mut self, config: &Self::InitCfg) {
         log::info!("SqliteIndex init");
 
-        let db_path = config.index_dir.join(SQL_INDEX_NAME);
-
-        self.conn = Some(Arc::new(Mutex::new(
-            Connection::establish(db_path.to_str().unwrap()).unwrap(),
-        )));
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-
-        diesel::sql_query(
-            r#"
-            CREATE TABLE IF NOT EXISTS file (
-                path TEXT NOT NULL,
-                last_indexed INTEGER NOT NULL,
-                PRIMARY KEY(path)
-            )
-            "#,
-        )
-        .execute(&mut *conn)
-        .unwrap();
-
-        let child_config = SqliteInitConfig {
-            vault_root_path: config.vault_root_path.clone(),
-            index_dir: config.index_dir.clone(),
-            db_path,
-            conn: Arc::clone(self.conn.as_ref().unwrap()),
-        };
+        self.store = Some(Arc::clone(&config.store));
+        self.store.as_ref().unwrap().init_schema(Table::File);
 
         log::info!("Index extension SqliteIndex initialized.");
 
-        // TODO: I have to come up with a better abstraction around the database connection.
-        // This pattern occurs in every extension that uses the database and has children.
-        // If I ever forget to drop the connection, I'll have a deadlock.
-        drop(conn);
         self.child_extensions
             .iter_mut()
-            .for_each(|ext| ext.init(&child_config));
+            .for_each(|ext| ext.init(config));
     }
 
     fn index(&mut self, new_note: &note::Note) {
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-
         let now = std::time::SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -95,30 +149,43 @@ impl IndexExt<'_> for SqliteIndex {
             path: new_note.rel_path.to_str().unwrap().to_owned(),
             // Set last_indexed to the current time.
             last_indexed: now.try_into().unwrap(),
+            mtime: file_mtime_secs(&new_note.absolute_path),
+            content_hash: file_content_hash(&new_note.absolute_path),
         };
 
-        diesel::insert_into(schema::file::table)
-            .values(&new_file)
-            .execute(&mut *conn)
-            .unwrap();
+        self.store.as_ref().unwrap().insert_file(new_file);
 
-        drop(conn);
         self.child_extensions
             .iter_mut()
             .for_each(|ext| ext.index(new_note));
     }
 
     fn remove(&mut self, rel_path: &Path) {
-        use schema::file::dsl::*;
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-        diesel::delete(schema::file::table)
-            .filter(path.eq(rel_path.to_str().unwrap()))
-            .execute(&mut *conn)
-            .unwrap();
-
-        drop(conn);
+        // Children first: with `PRAGMA foreign_keys = ON`, deleting the file
+        // row while a note still references it would be rejected.
         self.child_extensions
             .iter_mut()
             .for_each(|ext| ext.remove(rel_path));
+
+        let rel_path_owned = rel_path.to_str().unwrap().to_owned();
+        self.store.as_ref().unwrap().delete_file(rel_path_owned);
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) {
+        // Outermost key first, for readability: children reference `from`
+        // via a foreign key to `file.path`. `Inserter::flush` defers FK
+        // checks to COMMIT for the whole batch, so this ordering isn't
+        // load-bearing -- a child's `UPDATE ... SET ... = to` is free to
+        // land before `file`'s row does.
+        let from_owned = from.to_str().unwrap().to_owned();
+        let to_owned = to.to_str().unwrap().to_owned();
+        self.store
+            .as_ref()
+            .unwrap()
+            .rename_file(from_owned, to_owned);
+
+        self.child_extensions
+            .iter_mut()
+            .for_each(|ext| ext.rename(from, to));
     }
 }
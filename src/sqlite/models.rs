@@ -1,4 +1,4 @@
-use super::schema::{file, link, note};
+use super::schema::{embedding, file, link, note, tag};
 use diesel::prelude::*;
 
 #[derive(Queryable, Selectable, Insertable)]
@@ -7,6 +7,12 @@ use diesel::prelude::*;
 pub struct File {
     pub path: String,
     pub last_indexed: i32,
+    /// Unix timestamp the file carried on disk when last indexed, used as a
+    /// cheap pre-filter before hashing its contents.
+    pub mtime: i32,
+    /// BLAKE3 hex digest of the file contents when last indexed, used to
+    /// detect a touched-but-unchanged file and skip re-indexing it.
+    pub content_hash: String,
 }
 
 #[derive(Queryable, Selectable, Insertable)]
@@ -24,6 +30,33 @@ pub struct Link {
     pub from: String,
     pub to: String,
     pub text: Option<String>,
+    /// Heading or block (`^blockid`) the link points at within `to`, e.g.
+    /// the `heading` in `[[note#heading]]`.
+    pub anchor: Option<String>,
     pub start: i32,
     pub end: i32,
 }
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = tag)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Tag {
+    pub note: String,
+    pub name: String,
+    pub start: i32,
+    pub end: i32,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = embedding)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Embedding {
+    pub rel_path: String,
+    pub chunk_index: i32,
+    pub chunk_text: String,
+    /// Little-endian `f32` vector, as produced by the embedding model.
+    pub vector: Vec<u8>,
+    /// Precomputed L2 norm of `vector`, so cosine similarity at query time
+    /// doesn't have to recompute it for every stored row.
+    pub norm: f32,
+}
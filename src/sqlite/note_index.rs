@@ -1,14 +1,14 @@
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use crate::note;
 use crate::{indexer::IndexExt, markdown_index::MarkdownIndex};
-use diesel::prelude::*;
 
-use super::{models, schema, SqliteInitConfig};
+use super::store::{IndexStore, Table};
+use super::{models, SqliteInitConfig};
 
 pub struct NoteIndex {
-    conn: Option<Arc<Mutex<SqliteConnection>>>,
+    store: Option<Arc<dyn IndexStore>>,
     child_extensions:
         Vec<Box<dyn for<'a> IndexExt<'a, InitCfg = SqliteInitConfig, NoteIn = note::Note>>>,
 }
@@ -16,7 +16,7 @@ pub struct NoteIndex {
 impl NoteIndex {
     pub fn new() -> Self {
         Self {
-            conn: None,
+            store: None,
             child_extensions: vec![Box::new(MarkdownIndex::new())],
         }
     }
@@ -26,22 +26,9 @@ impl IndexExt<'_> for NoteIndex {
     type InitCfg = SqliteInitConfig;
     type NoteIn = note::Note;
     fn init(&mut self, config: &Self::InitCfg) {
-        self.conn = Some(Arc::clone(&config.conn));
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-        diesel::sql_query(
-            r#"
-                    CREATE TABLE IF NOT EXISTS note (
-                        vault_path TEXT NOT NULL,
-                        file TEXT NOT NULL,
-                        PRIMARY KEY(file),
-                        FOREIGN KEY(file) REFERENCES file(path)
-                    )
-                "#,
-        )
-        .execute(&mut *conn)
-        .unwrap();
+        self.store = Some(Arc::clone(&config.store));
+        self.store.as_ref().unwrap().init_schema(Table::Note);
 
-        drop(conn);
         log::info!("Index extension NoteIndex initialized.");
         self.child_extensions
             .iter_mut()
@@ -49,34 +36,48 @@ impl IndexExt<'_> for NoteIndex {
     }
 
     fn index(&mut self, new_note: &note::Note) {
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
         let new_row = models::Note {
             file: new_note.rel_path.to_str().unwrap().to_owned(),
             vault_path: new_note.vault_path.to_str().unwrap().to_owned(),
         };
 
-        diesel::insert_into(schema::note::table)
-            .values(&new_row)
-            .execute(&mut *conn)
-            .unwrap();
+        self.store.as_ref().unwrap().insert_note(new_row);
 
-        drop(conn);
         self.child_extensions.iter_mut().for_each(|ext| {
             ext.index(new_note);
         });
     }
 
     fn remove(&mut self, path: &Path) {
-        let mut conn = self.conn.as_ref().unwrap().lock().unwrap();
-        use schema::note::dsl::*;
-        diesel::delete(schema::note::table)
-            .filter(file.eq(path.to_str().unwrap()))
-            .execute(&mut *conn)
-            .unwrap();
-
-        drop(conn);
+        // Children first: with `PRAGMA foreign_keys = ON`, deleting the note
+        // row while a link still references it would be rejected.
         self.child_extensions.iter_mut().for_each(|ext| {
             ext.remove(path);
         });
+
+        let path_owned = path.to_str().unwrap().to_owned();
+        self.store.as_ref().unwrap().delete_note(path_owned);
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) {
+        // Outermost key first, for readability: children (link/tag/
+        // embedding) reference `from` via a foreign key to `note.file`.
+        // The FK itself isn't actually satisfied until every UPDATE in the
+        // batch has run -- `Inserter::flush` defers the checks to COMMIT --
+        // so this ordering doesn't have to be parent-before-child to work.
+        let from_owned = from.to_str().unwrap().to_owned();
+        let to_owned = to.to_str().unwrap().to_owned();
+        let new_vault_path = note::vault_path_from_relative_path(to)
+            .to_str()
+            .unwrap()
+            .to_owned();
+        self.store
+            .as_ref()
+            .unwrap()
+            .rename_note(from_owned, to_owned, new_vault_path);
+
+        self.child_extensions
+            .iter_mut()
+            .for_each(|ext| ext.rename(from, to));
     }
 }
@@ -4,6 +4,8 @@ diesel::table! {
     file (path) {
         path -> Text,
         last_indexed -> Integer,
+        mtime -> Integer,
+        content_hash -> Text,
     }
 }
 
@@ -12,6 +14,7 @@ diesel::table! {
         from -> Text,
         to -> Text,
         text -> Nullable<Text>,
+        anchor -> Nullable<Text>,
         start -> Integer,
         end -> Integer,
     }
@@ -24,7 +27,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    embedding (rel_path, chunk_index) {
+        rel_path -> Text,
+        chunk_index -> Integer,
+        chunk_text -> Text,
+        vector -> Binary,
+        norm -> Float,
+    }
+}
+
+diesel::table! {
+    tag (note, start) {
+        note -> Text,
+        name -> Text,
+        start -> Integer,
+        end -> Integer,
+    }
+}
+
+diesel::joinable!(embedding -> note (rel_path));
 diesel::joinable!(link -> note (from));
 diesel::joinable!(note -> file (file));
+diesel::joinable!(tag -> note (note));
 
-diesel::allow_tables_to_appear_in_same_query!(file, link, note,);
+diesel::allow_tables_to_appear_in_same_query!(embedding, file, link, note, tag,);
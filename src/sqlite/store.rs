@@ -0,0 +1,419 @@
+//! Storage-backend abstraction separating the extension tree from the
+//! concrete engine underneath it. Every `IndexExt` only ever reaches for one
+//! of the narrow, per-table operations below -- never a raw connection, a
+//! closure over one, or raw SQL -- so a different embedded engine (an
+//! LMDB- or redb-style KV store, say) could be dropped in behind
+//! `IndexStore` without touching any extension's indexing logic.
+//! `SqliteStore` is the only implementation today.
+
+use std::sync::{Arc, Condvar, Mutex};
+
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Text};
+use diesel::SqliteConnection;
+
+use crate::indexer::writer::WriteOp;
+
+use super::{models, schema, ReadPool};
+
+/// The logical schema `IndexStore::init_schema` creates one of, so that
+/// extensions declare *what* table they own rather than handing a backend
+/// raw DDL it may not even understand.
+pub enum Table {
+    File,
+    Note,
+    Link,
+    Tag,
+    Embedding,
+    Fts,
+}
+
+/// A full-text search hit: the note it came from, its BM25 rank (more
+/// negative is a better match, per FTS5's convention), and a `snippet()`
+/// excerpt with matched terms wrapped in `[...]` for display.
+#[derive(Debug, QueryableByName)]
+pub struct FtsHit {
+    #[diesel(sql_type = Text)]
+    pub rel_path: String,
+    #[diesel(sql_type = Double)]
+    pub rank: f64,
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+}
+
+/// What every `IndexExt` needs from whatever engine is backing the index:
+/// one narrow operation per table for the writes it makes, and the couple of
+/// typed reads `embedding_index::search` and `fts_index::search` run. Nothing
+/// here mentions `diesel` or `SqliteConnection`, so a non-SQL backend can
+/// implement every method in terms of whatever storage it actually uses.
+pub trait IndexStore: Send + Sync {
+    /// Create `table`'s schema if it doesn't already exist.
+    fn init_schema(&self, table: Table);
+
+    fn insert_file(&self, row: models::File);
+    fn delete_file(&self, path: String);
+    fn rename_file(&self, from: String, to: String);
+
+    fn insert_note(&self, row: models::Note);
+    fn delete_note(&self, file: String);
+    fn rename_note(&self, from: String, to: String, vault_path: String);
+
+    fn insert_links(&self, links: Vec<models::Link>);
+    fn delete_links(&self, from: String);
+    /// Repoints outgoing links from `from` to `to`, and rewrites any link
+    /// elsewhere in the vault that targeted `from`'s vault path so it
+    /// targets `to`'s instead.
+    fn rename_links(&self, from: String, to: String, old_vault_path: String, new_vault_path: String);
+
+    fn insert_tags(&self, tags: Vec<models::Tag>);
+    fn delete_tags(&self, note: String);
+    fn rename_tags(&self, from: String, to: String);
+
+    fn insert_embeddings(&self, rows: Vec<models::Embedding>);
+    fn delete_embeddings(&self, rel_path: String);
+    fn rename_embeddings(&self, from: String, to: String);
+    /// Every stored chunk embedding, for `embedding_index::search`'s brute
+    /// force cosine scan.
+    fn all_embeddings(&self) -> Vec<models::Embedding>;
+
+    fn insert_fts(&self, rel_path: String, title: String, body: String);
+    fn delete_fts(&self, rel_path: String);
+    fn rename_fts(&self, from: String, to: String);
+    /// Rank `query` against the indexed title/body using FTS5's `bm25()`
+    /// scoring and return the top `limit` hits, best match first.
+    fn search_fts(&self, query: &str, limit: usize) -> Vec<FtsHit>;
+
+    /// Block until every write submitted before this call is durable.
+    /// `Indexer::wait_until_drained` calls this after the embedding batcher
+    /// has handed over its own pending writes, so `process()` can't return
+    /// while any of them are still only queued rather than committed.
+    fn flush(&self);
+}
+
+/// The default (and so far only) `IndexStore`: a single SQLite file behind
+/// diesel, writes funneled through one dedicated connection while reads use
+/// a small WAL-mode pool.
+pub struct SqliteStore {
+    conn: Arc<Mutex<SqliteConnection>>,
+    write_sender: crossbeam_channel::Sender<WriteOp>,
+    read_pool: ReadPool,
+}
+
+impl SqliteStore {
+    pub fn new(
+        conn: Arc<Mutex<SqliteConnection>>,
+        write_sender: crossbeam_channel::Sender<WriteOp>,
+        read_pool: ReadPool,
+    ) -> Self {
+        Self {
+            conn,
+            write_sender,
+            read_pool,
+        }
+    }
+
+    fn write(&self, op: WriteOp) {
+        self.write_sender.send(op).unwrap();
+    }
+}
+
+impl IndexStore for SqliteStore {
+    fn init_schema(&self, table: Table) {
+        let ddl = match table {
+            Table::File => {
+                r#"
+                CREATE TABLE IF NOT EXISTS file (
+                    path TEXT NOT NULL,
+                    last_indexed INTEGER NOT NULL,
+                    mtime INTEGER NOT NULL,
+                    content_hash TEXT NOT NULL,
+                    PRIMARY KEY(path)
+                )
+                "#
+            }
+            Table::Note => {
+                r#"
+                CREATE TABLE IF NOT EXISTS note (
+                    vault_path TEXT NOT NULL,
+                    file TEXT NOT NULL,
+                    PRIMARY KEY(file),
+                    FOREIGN KEY(file) REFERENCES file(path)
+                )
+                "#
+            }
+            Table::Link => {
+                r#"
+                CREATE TABLE IF NOT EXISTS link (
+                    "from" TEXT NOT NULL,
+                    "to" TEXT NOT NULL,
+                    "text" TEXT,
+                    "anchor" TEXT,
+                    "start" INTEGER,
+                    "end" INTEGER,
+                    PRIMARY KEY("from", "start"),
+                    FOREIGN KEY("from") REFERENCES note (file)
+                )
+                "#
+            }
+            Table::Tag => {
+                r#"
+                CREATE TABLE IF NOT EXISTS tag (
+                    "note" TEXT NOT NULL,
+                    "name" TEXT NOT NULL,
+                    "start" INTEGER NOT NULL,
+                    "end" INTEGER NOT NULL,
+                    PRIMARY KEY("note", "start"),
+                    FOREIGN KEY("note") REFERENCES note (file)
+                )
+                "#
+            }
+            Table::Embedding => {
+                r#"
+                CREATE TABLE IF NOT EXISTS embedding (
+                    "rel_path" TEXT NOT NULL,
+                    "chunk_index" INTEGER NOT NULL,
+                    "chunk_text" TEXT NOT NULL,
+                    "vector" BLOB NOT NULL,
+                    "norm" REAL NOT NULL,
+                    PRIMARY KEY("rel_path", "chunk_index"),
+                    FOREIGN KEY("rel_path") REFERENCES note (file)
+                )
+                "#
+            }
+            Table::Fts => {
+                r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS note_fts USING fts5(
+                    rel_path UNINDEXED,
+                    title,
+                    body
+                )
+                "#
+            }
+        };
+        let mut conn = self.conn.lock().unwrap();
+        diesel::sql_query(ddl).execute(&mut *conn).unwrap();
+    }
+
+    fn insert_file(&self, row: models::File) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::insert_into(schema::file::table)
+                .values(&row)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn delete_file(&self, path: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::file::dsl;
+            diesel::delete(schema::file::table)
+                .filter(dsl::path.eq(&path))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn rename_file(&self, from: String, to: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::file::dsl;
+            diesel::update(schema::file::table)
+                .filter(dsl::path.eq(&from))
+                .set(dsl::path.eq(&to))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn insert_note(&self, row: models::Note) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::insert_into(schema::note::table)
+                .values(&row)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn delete_note(&self, file: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::note::dsl;
+            diesel::delete(schema::note::table)
+                .filter(dsl::file.eq(&file))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn rename_note(&self, from: String, to: String, vault_path: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::note::dsl;
+            diesel::update(schema::note::table)
+                .filter(dsl::file.eq(&from))
+                .set((dsl::file.eq(&to), dsl::vault_path.eq(&vault_path)))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn insert_links(&self, links: Vec<models::Link>) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::insert_into(schema::link::table)
+                .values(links)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn delete_links(&self, from: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::link::dsl;
+            diesel::delete(schema::link::table)
+                .filter(dsl::from.eq(&from))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn rename_links(&self, from: String, to: String, old_vault_path: String, new_vault_path: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::link::dsl;
+            diesel::update(schema::link::table)
+                .filter(dsl::from.eq(&from))
+                .set(dsl::from.eq(&to))
+                .execute(conn)
+                .unwrap();
+            diesel::update(schema::link::table)
+                .filter(dsl::to.eq(&old_vault_path))
+                .set(dsl::to.eq(&new_vault_path))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn insert_tags(&self, tags: Vec<models::Tag>) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::insert_into(schema::tag::table)
+                .values(tags)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn delete_tags(&self, note: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::tag::dsl;
+            diesel::delete(schema::tag::table)
+                .filter(dsl::note.eq(&note))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn rename_tags(&self, from: String, to: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::tag::dsl;
+            diesel::update(schema::tag::table)
+                .filter(dsl::note.eq(&from))
+                .set(dsl::note.eq(&to))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn insert_embeddings(&self, rows: Vec<models::Embedding>) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::insert_into(schema::embedding::table)
+                .values(rows)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn delete_embeddings(&self, rel_path: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::embedding::dsl;
+            diesel::delete(schema::embedding::table)
+                .filter(dsl::rel_path.eq(&rel_path))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn rename_embeddings(&self, from: String, to: String) {
+        self.write(WriteOp::exec(move |conn| {
+            use schema::embedding::dsl;
+            diesel::update(schema::embedding::table)
+                .filter(dsl::rel_path.eq(&from))
+                .set(dsl::rel_path.eq(&to))
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn all_embeddings(&self) -> Vec<models::Embedding> {
+        let mut conn = self.read_pool.get().unwrap();
+        schema::embedding::table
+            .select(models::Embedding::as_select())
+            .load(&mut conn)
+            .unwrap()
+    }
+
+    fn insert_fts(&self, rel_path: String, title: String, body: String) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::sql_query("INSERT INTO note_fts (rel_path, title, body) VALUES (?, ?, ?)")
+                .bind::<Text, _>(&rel_path)
+                .bind::<Text, _>(&title)
+                .bind::<Text, _>(&body)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn delete_fts(&self, rel_path: String) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::sql_query("DELETE FROM note_fts WHERE rel_path = ?")
+                .bind::<Text, _>(&rel_path)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn rename_fts(&self, from: String, to: String) {
+        self.write(WriteOp::exec(move |conn| {
+            diesel::sql_query("UPDATE note_fts SET rel_path = ? WHERE rel_path = ?")
+                .bind::<Text, _>(&to)
+                .bind::<Text, _>(&from)
+                .execute(conn)
+                .unwrap();
+        }));
+    }
+
+    fn search_fts(&self, query: &str, limit: usize) -> Vec<FtsHit> {
+        let mut conn = self.read_pool.get().unwrap();
+        diesel::sql_query(
+            r#"
+                SELECT
+                    rel_path,
+                    bm25(note_fts) AS rank,
+                    snippet(note_fts, 2, '[', ']', '...', 10) AS snippet
+                FROM note_fts
+                WHERE note_fts MATCH ?
+                ORDER BY rank
+                LIMIT ?
+            "#,
+        )
+        .bind::<Text, _>(query)
+        .bind::<BigInt, _>(limit as i64)
+        .load::<FtsHit>(&mut *conn)
+        .unwrap()
+    }
+
+    fn flush(&self) {
+        let barrier = Arc::new((Mutex::new(1_usize), Condvar::new()));
+        self.write(WriteOp::Barrier(Arc::clone(&barrier)));
+        let (lock, cvar) = &*barrier;
+        let mut count = lock.lock().unwrap();
+        while *count > 0 {
+            count = cvar.wait(count).unwrap();
+        }
+    }
+}
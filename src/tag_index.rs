@@ -0,0 +1,65 @@
+use crate::{
+    indexer::IndexExt,
+    markdown_index::MarkdownNote,
+    sqlite::{
+        models,
+        store::{IndexStore, Table},
+        SqliteInitConfig,
+    },
+    tag_parser::Tag,
+};
+use std::{path::Path, sync::Arc};
+
+pub struct TagIndex {
+    store: Option<Arc<dyn IndexStore>>,
+}
+
+impl TagIndex {
+    pub fn new() -> Self {
+        Self { store: None }
+    }
+}
+
+impl<'a> IndexExt<'a> for TagIndex {
+    type InitCfg = SqliteInitConfig;
+    type NoteIn = MarkdownNote<'a>;
+
+    fn init(&mut self, config: &Self::InitCfg) {
+        self.store = Some(Arc::clone(&config.store));
+        self.store.as_ref().unwrap().init_schema(Table::Tag);
+        log::info!("Index extension TagIndex initialized.");
+    }
+
+    fn index(&mut self, md_note: &MarkdownNote<'a>) {
+        let mut tags = Vec::new();
+        md_note.markdown.walk(|node, _| {
+            if node.is::<Tag>() {
+                let tag = node.cast::<Tag>().unwrap();
+                let (start, end) = node.srcmap.unwrap().get_byte_offsets();
+                log::debug!("Found tag: {:?}", tag);
+                tags.push(models::Tag {
+                    note: md_note.note.rel_path.to_str().unwrap().to_owned(),
+                    name: tag.name.to_owned(),
+                    start: start.try_into().unwrap(),
+                    end: end.try_into().unwrap(),
+                });
+            }
+        });
+
+        self.store.as_ref().unwrap().insert_tags(tags);
+    }
+
+    fn remove(&mut self, rel_path: &Path) {
+        let rel_path_owned = rel_path.to_str().unwrap().to_owned();
+        self.store.as_ref().unwrap().delete_tags(rel_path_owned);
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) {
+        let from_owned = from.to_str().unwrap().to_owned();
+        let to_owned = to.to_str().unwrap().to_owned();
+        self.store
+            .as_ref()
+            .unwrap()
+            .rename_tags(from_owned, to_owned);
+    }
+}
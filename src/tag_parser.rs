@@ -0,0 +1,119 @@
+//! An extension for markdown-it that parses inline tags.
+//! A tag is a marker that looks like this: `#tag` or, hierarchically,
+//! `#area/project`.
+
+use markdown_it::{
+    parser::inline::{InlineRule, InlineState},
+    MarkdownIt, Node, NodeValue, Renderer,
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub name: String,
+}
+
+impl NodeValue for Tag {
+    fn render(&self, _: &Node, fmt: &mut dyn Renderer) {
+        fmt.text_raw(&format!("#{}", self.name));
+    }
+}
+
+pub fn add(md: &mut MarkdownIt) {
+    md.inline.add_rule::<TagScanner>();
+}
+
+// Create a regex to match the tag and capture its name, including nested
+// path components like `area/project`.
+pub static TAG_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^#[\w][\w/-]*").expect("Failed to compile TAG_RE regex."));
+
+#[doc(hidden)]
+pub struct TagScanner;
+impl InlineRule for TagScanner {
+    const MARKER: char = '#';
+    fn run(state: &mut InlineState) -> Option<(Node, usize)> {
+        let capture: Option<&str> = TAG_RE
+            .captures(&state.src[state.pos..state.pos_max])?
+            .get(0)
+            .map(|m| m.as_str());
+
+        match capture {
+            Some(capture) => {
+                // The capture includes the leading `#`, so we need to remove it.
+                let name = &capture[1..];
+
+                let node = Node::new(Tag {
+                    name: name.to_string(),
+                });
+                Some((node, capture.len()))
+            }
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> Node {
+        let mut md = markdown_it::MarkdownIt::new();
+        markdown_it::plugins::cmark::add(&mut md);
+        add(&mut md);
+        md.parse(src)
+    }
+
+    fn find_tag(src: &str) -> Option<Tag> {
+        let tree = parse(src);
+        let mut found = None;
+        tree.walk(|node, _| {
+            if node.is::<Tag>() {
+                found = Some(node.cast::<Tag>().unwrap().clone());
+            }
+        });
+        found
+    }
+
+    #[test]
+    fn test_parse_bare_tag() {
+        let tag = find_tag("a #tag in some text").expect("expected to find a Tag node");
+        assert_eq!(tag.name, "tag");
+    }
+
+    #[test]
+    fn test_parse_hierarchical_tag() {
+        let tag = find_tag("a #area/project tag").expect("expected to find a Tag node");
+        assert_eq!(tag.name, "area/project");
+    }
+
+    #[test]
+    fn test_parse_tag_with_hyphen() {
+        let tag = find_tag("a #my-tag here").expect("expected to find a Tag node");
+        assert_eq!(tag.name, "my-tag");
+    }
+
+    #[test]
+    fn test_hash_followed_by_space_is_not_a_tag() {
+        // `TAG_RE` requires a word character immediately after the `#`, so
+        // a `#` that's just punctuation (not followed by one) isn't
+        // captured as a tag.
+        assert!(find_tag("a # not a tag").is_none());
+    }
+
+    #[test]
+    fn test_srcmap_covers_the_whole_match() {
+        let tree = parse("#tag and then some trailing text");
+        let mut found = false;
+        tree.walk(|node, _| {
+            if node.is::<Tag>() {
+                let (start, end) = node.srcmap.unwrap().get_byte_offsets();
+                assert_eq!(start, 0);
+                assert_eq!(end, "#tag".len());
+                found = true;
+            }
+        });
+        assert!(found, "expected to find a Tag node");
+    }
+}
@@ -6,21 +6,38 @@ use notify::{recommended_watcher, RecommendedWatcher, RecursiveMode, Watcher};
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
-    sync::mpsc::{channel, Receiver, Sender},
-    time::UNIX_EPOCH,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, UNIX_EPOCH},
 };
 
-use crate::sqlite::with_db_conn;
+use crate::sqlite::{models, with_db_conn};
 
 #[derive(Clone, Debug)]
 pub enum IndexEvent {
     // Remove must be idempotent, it may be called for non-existing notes.
     Remove(PathBuf),
     Add(PathBuf),
+    // A note moved or was renamed without its content changing, so its
+    // outgoing/incoming links can be rewritten in place instead of dropped.
+    Rename { from: PathBuf, to: PathBuf },
+}
+
+impl IndexEvent {
+    /// The path events for the same file should be routed by, so that e.g. a
+    /// `Remove` and `Add` for the same path (the watcher's changed-file
+    /// pattern) always reach the indexer's worker pool in order. A `Rename`
+    /// routes on `from`, the path whose row(s) it repoints.
+    pub fn path(&self) -> &Path {
+        match self {
+            IndexEvent::Remove(path) | IndexEvent::Add(path) => path,
+            IndexEvent::Rename { from, .. } => from,
+        }
+    }
 }
 
 pub struct DirWatcher {
     vault_root_path: Box<Path>,
+    db_path: PathBuf,
     watcher: RecommendedWatcher,
     file_event_receiver: Receiver<Result<notify::Event, notify::Error>>,
     index_event_sender: Sender<IndexEvent>,
@@ -28,6 +45,57 @@ pub struct DirWatcher {
     file_filter: Box<dyn Fn(&Path) -> bool>,
 }
 
+/// Window over which raw `notify` events for the same path are buffered and
+/// collapsed into one net operation, so a single editor save (which often
+/// fires several `Modify(Data)` events) doesn't reindex the same file over
+/// and over.
+const DEBOUNCE_WINDOW: Duration = Duration::from_millis(300);
+
+/// The net effect a burst of raw events has had on a path, once debounced.
+#[derive(Debug)]
+enum PendingOp {
+    Add,
+    Remove,
+}
+
+/// Whether `rel_path`'s on-disk contents have actually changed since it was
+/// last indexed, not just its mtime. Looks up the previously stored
+/// mtime/hash for `rel_path` and uses the mtime as a cheap pre-filter, so
+/// hashing only happens once the mtime has advanced. A file with no
+/// previous record is always treated as changed. This is what keeps a
+/// `Modify(Data)` event for a touched-but-unchanged file (e.g. a save with
+/// no edits) from triggering a needless re-parse and re-embedding.
+fn file_content_changed(db_path: &Path, rel_path: &Path, absolute_path: &Path) -> bool {
+    use diesel::prelude::*;
+
+    let previous = with_db_conn(db_path, |conn| {
+        crate::sqlite::schema::file::dsl::file
+            .find(rel_path.to_str().unwrap())
+            .first::<models::File>(conn)
+            .optional()
+            .unwrap()
+    });
+
+    let modified: i32 = std::fs::metadata(absolute_path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| {
+            modified
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i32)
+                .unwrap_or(0)
+        })
+        .unwrap_or(0);
+
+    match previous {
+        Some(ref file) if file.mtime >= modified => false,
+        Some(ref file) => {
+            let contents = std::fs::read(absolute_path).unwrap();
+            blake3::hash(&contents).to_hex().to_string() != file.content_hash
+        }
+        None => true,
+    }
+}
+
 pub fn file_has_no_hidden_component(path: &Path) -> bool {
     !path.components().any(|c| {
         c.as_os_str().to_str().unwrap().starts_with('.')
@@ -37,19 +105,18 @@ pub fn file_has_no_hidden_component(path: &Path) -> bool {
 
 impl DirWatcher {
     pub fn new(
-        path: &str,
+        init_config: &crate::indexer::IndexerInitConfig,
         index_event_sender: Sender<IndexEvent>,
         file_filter: Box<dyn Fn(&Path) -> bool>,
     ) -> Self {
-        let path = Path::new(path);
-
         let (tx, rx) = channel();
-        let config = notify::Config::default().with_compare_contents(false);
+        let notify_config = notify::Config::default().with_compare_contents(false);
         let mut watcher: RecommendedWatcher = recommended_watcher(tx).unwrap();
-        watcher.configure(config).unwrap();
+        watcher.configure(notify_config).unwrap();
 
         Self {
-            vault_root_path: Box::from(path),
+            vault_root_path: Box::from(init_config.vault_root_path.as_path()),
+            db_path: init_config.index_dir.join(crate::sqlite::SQL_INDEX_NAME),
             watcher,
             file_event_receiver: rx,
             index_event_sender,
@@ -58,23 +125,24 @@ impl DirWatcher {
     }
 
     /// Sync the filesystem and the index.
-    /// Iterate over all files in the vault and check if they have been modified
-    /// since the last run.
+    /// Iterate over all files in the vault and check if they have changed
+    /// since the last run, so that restarting the indexer is cheap even for
+    /// a large vault that was untouched since the last exit.
     fn sync_fs_and_index(&mut self) {
         use diesel::RunQueryDsl;
 
-        // Get the last run times from the database.
+        // Get the previously indexed mtime/hash from the database.
         // As the directory is traversed, the entries are removed from this map.
         // At the end, all remaining entries must have been deleted while the
         // indexer was not running and are therefore removed from the index.
-        let mut file_map: HashMap<String, i32> = HashMap::new();
-        with_db_conn(|conn| {
+        let mut file_map: HashMap<String, crate::sqlite::models::File> = HashMap::new();
+        with_db_conn(&self.db_path, |conn| {
             for f in crate::sqlite::schema::file::dsl::file
                 .load::<crate::sqlite::models::File>(conn)
                 .unwrap()
                 .into_iter()
             {
-                file_map.insert(f.path, f.last_indexed);
+                file_map.insert(f.path.clone(), f);
             }
         });
 
@@ -83,23 +151,18 @@ impl DirWatcher {
             let entry = entry.unwrap();
             let metadata = entry.metadata().unwrap();
             if metadata.is_file() && (self.file_filter)(entry.path()) {
-                let modified = metadata
-                    .modified()
-                    .unwrap()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-
                 let rel_path = self.relative_path_from_absolute_path(entry.path());
-                let last_indexed = file_map.remove(rel_path.to_str().unwrap()).unwrap_or(0);
-
-                if last_indexed < modified.try_into().unwrap() {
-                    log::debug!(
-                        "File {:?} has been modified at {} which is after the last index time {}.",
-                        entry.path(),
-                        modified,
-                        last_indexed
-                    );
+                file_map.remove(rel_path.to_str().unwrap());
+
+                // The mtime is a cheap pre-filter: only hash the file's
+                // contents (the expensive part) once the mtime advanced, and
+                // only re-index it if the contents actually changed. This
+                // means a touched-but-unchanged file (e.g. a git checkout)
+                // doesn't trigger a needless re-parse.
+                let changed = file_content_changed(&self.db_path, &rel_path, entry.path());
+
+                if changed {
+                    log::debug!("File {:?} has changed since it was last indexed.", entry.path());
                     self.emit_index_event(IndexEvent::Remove(rel_path.clone()));
                     self.emit_index_event(IndexEvent::Add(rel_path));
                 }
@@ -119,11 +182,52 @@ impl DirWatcher {
             .unwrap();
 
         self.sync_fs_and_index();
+        self.run_event_loop();
+    }
 
+    /// Drain `file_event_receiver` into a per-path pending-operation map,
+    /// flushing the accumulated `IndexEvent`s once `DEBOUNCE_WINDOW` elapses
+    /// with no new events. A single editor save often fires several raw
+    /// `notify` events for the same path in quick succession; buffering
+    /// them collapses that burst into one net operation instead of
+    /// reindexing (and re-embedding) the same file over and over.
+    fn run_event_loop(&mut self) {
+        let mut pending: HashMap<PathBuf, PendingOp> = HashMap::new();
         loop {
-            match self.file_event_receiver.recv().unwrap() {
-                Ok(event) => self.handle_event(event),
-                Err(e) => log::error!("INotifyWatcher error: {:?}", e),
+            match self.file_event_receiver.recv_timeout(DEBOUNCE_WINDOW) {
+                Ok(Ok(event)) => self.buffer_event(event, &mut pending),
+                Ok(Err(e)) => log::error!("INotifyWatcher error: {:?}", e),
+                Err(RecvTimeoutError::Timeout) => self.flush_pending(&mut pending),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    /// Merge a newly observed `op` for `rel_path` into the pending map,
+    /// latest-wins. A remove after a not-yet-flushed add still nets to a
+    /// `Remove`: that add may come from a `Modify(Data)` of a file the index
+    /// already holds. For a file created then deleted again within the
+    /// debounce window, the resulting `Remove` leaves the index untouched,
+    /// since removal is idempotent.
+    fn merge_pending(pending: &mut HashMap<PathBuf, PendingOp>, rel_path: PathBuf, op: PendingOp) {
+        pending.insert(rel_path, op);
+    }
+
+    /// Emit the `IndexEvent`s for every path netted since the last flush.
+    /// A pending `Add` still goes through the content-hash check, so a
+    /// burst that nets to "the file was touched" but didn't actually change
+    /// its contents still doesn't trigger a re-parse and re-embedding.
+    fn flush_pending(&self, pending: &mut HashMap<PathBuf, PendingOp>) {
+        for (rel_path, op) in pending.drain() {
+            match op {
+                PendingOp::Add => {
+                    let absolute_path = self.vault_root_path.join(&rel_path);
+                    if file_content_changed(&self.db_path, &rel_path, &absolute_path) {
+                        self.emit_index_event(IndexEvent::Remove(rel_path.clone()));
+                        self.emit_index_event(IndexEvent::Add(rel_path));
+                    }
+                }
+                PendingOp::Remove => self.emit_index_event(IndexEvent::Remove(rel_path)),
             }
         }
     }
@@ -133,46 +237,69 @@ impl DirWatcher {
         self.index_event_sender.send(event).unwrap();
     }
 
-    fn handle_event(&self, event: notify::event::Event) {
+    fn buffer_event(&self, event: notify::event::Event, pending: &mut HashMap<PathBuf, PendingOp>) {
         use notify::event::EventKind::{Create, Modify, Remove};
         use notify::event::ModifyKind;
         use notify::event::RenameMode;
         match event.kind {
-            /* Apparently, the RenameMode::Both event is emitted **in addition** to the
-               RenameMode::From and RenameMode::To events. So we don't need to handle it
-               here.
-
+            // The RenameMode::Both event is emitted **in addition** to the
+            // RenameMode::From and RenameMode::To events, and unlike them
+            // carries both paths at once, so it's the only one we need to
+            // act on; the individual From/To events below are ignored to
+            // avoid double-processing the same move.
             Modify(ModifyKind::Name(RenameMode::Both)) => {
-                log::debug!("Handling event: {:?}", event);
                 assert!(event.paths.len() == 2);
-                let from_path = event.paths.first().unwrap();
-                let to_path = event.paths.last().unwrap();
-                if (self.file_filter)(from_path) {
-                    let rel_path = self.relative_path_from_absolute_path(from_path);
-                    self.emit_index_event(IndexEvent::Remove(rel_path));
-                }
-                if (self.file_filter)(to_path) {
-                    let rel_path = self.relative_path_from_absolute_path(to_path);
-                    self.emit_index_event(IndexEvent::Add(rel_path));
-                }
-            }*/
-            Modify(ModifyKind::Name(RenameMode::From)) => {
-                assert!(event.paths.len() == 1);
-                let from_path = event.paths.first().unwrap();
-                if (self.file_filter)(from_path) {
-                    log::debug!("Handling event: {:?}", event);
-                    let rel_path = self.relative_path_from_absolute_path(from_path);
-                    self.emit_index_event(IndexEvent::Remove(rel_path));
+                log::debug!("Handling event: {:?}", event);
+                let from_path = &event.paths[0];
+                let to_path = &event.paths[1];
+                match ((self.file_filter)(from_path), (self.file_filter)(to_path)) {
+                    (true, true) => {
+                        let from_rel = self.relative_path_from_absolute_path(from_path);
+                        let to_rel = self.relative_path_from_absolute_path(to_path);
+                        // A rename carries from/to semantics the Add/Remove
+                        // netting below doesn't model, so it bypasses the
+                        // pending map and is emitted right away; any op
+                        // still pending for either path is now stale.
+                        let from_pending = pending.remove(&from_rel);
+                        pending.remove(&to_rel);
+                        match from_pending {
+                            // `from` has an unflushed add: it was either
+                            // created within this debounce window (no
+                            // `file`/`note` row yet for the `Rename`
+                            // handler's `UPDATE ... WHERE path = from` to
+                            // act on) or modified, so its rows are stale.
+                            // Remove `from` (a no-op if it was never
+                            // indexed) and index `to` fresh instead.
+                            Some(PendingOp::Add) => {
+                                self.emit_index_event(IndexEvent::Remove(from_rel));
+                                Self::merge_pending(pending, to_rel, PendingOp::Add);
+                            }
+                            _ => {
+                                self.emit_index_event(IndexEvent::Rename {
+                                    from: from_rel,
+                                    to: to_rel,
+                                });
+                            }
+                        }
+                    }
+                    // `from_path` is filtered out (e.g. an editor's hidden
+                    // temp file), so this is the atomic-save pattern rather
+                    // than a real move: reindex `to_path` in place instead
+                    // of treating it as a rename, which would otherwise
+                    // rewrite link rows for no reason.
+                    (false, true) => {
+                        let to_rel = self.relative_path_from_absolute_path(to_path);
+                        Self::merge_pending(pending, to_rel, PendingOp::Add);
+                    }
+                    (true, false) => {
+                        let from_rel = self.relative_path_from_absolute_path(from_path);
+                        Self::merge_pending(pending, from_rel, PendingOp::Remove);
+                    }
+                    (false, false) => {}
                 }
             }
-            Modify(ModifyKind::Name(RenameMode::To)) => {
-                assert!(event.paths.len() == 1);
-                let to_path = event.paths.first().unwrap();
-                if (self.file_filter)(to_path) {
-                    log::debug!("Handling event: {:?}", event);
-                    let rel_path = self.relative_path_from_absolute_path(to_path);
-                    self.emit_index_event(IndexEvent::Add(rel_path));
-                }
+            Modify(ModifyKind::Name(RenameMode::From | RenameMode::To)) => {
+                // Handled together via the RenameMode::Both event above.
             }
             Modify(ModifyKind::Data(_)) => {
                 assert!(event.paths.len() == 1);
@@ -180,8 +307,7 @@ impl DirWatcher {
                     if (self.file_filter)(path) {
                         log::debug!("Handling event: {:?}", event);
                         let rel_path = self.relative_path_from_absolute_path(path);
-                        self.emit_index_event(IndexEvent::Remove(rel_path.clone()));
-                        self.emit_index_event(IndexEvent::Add(rel_path));
+                        Self::merge_pending(pending, rel_path, PendingOp::Add);
                     }
                 });
             }
@@ -190,9 +316,8 @@ impl DirWatcher {
                 event.paths.iter().for_each(|path| {
                     if (self.file_filter)(path) {
                         log::debug!("Handling event: {:?}", event);
-                        self.emit_index_event(IndexEvent::Add(
-                            self.relative_path_from_absolute_path(path),
-                        ));
+                        let rel_path = self.relative_path_from_absolute_path(path);
+                        Self::merge_pending(pending, rel_path, PendingOp::Add);
                     }
                 });
             }
@@ -201,9 +326,8 @@ impl DirWatcher {
                 event.paths.iter().for_each(|path| {
                     if (self.file_filter)(path) {
                         log::debug!("Handling event: {:?}", event);
-                        self.emit_index_event(IndexEvent::Remove(
-                            self.relative_path_from_absolute_path(path),
-                        ));
+                        let rel_path = self.relative_path_from_absolute_path(path);
+                        Self::merge_pending(pending, rel_path, PendingOp::Remove);
                     }
                 });
             }
@@ -231,6 +355,7 @@ impl DirWatcher {
 mod tests {
     use std::sync::mpsc::Sender;
 
+    use crate::indexer::IndexerInitConfig;
     use crate::watcher::file_has_no_hidden_component;
     use proptest::prelude::*;
 
@@ -242,8 +367,12 @@ mod tests {
         // Create a temporary directory.
         let temp_dir = tempfile::tempdir().unwrap();
 
+        let config = IndexerInitConfig::new(
+            temp_dir.path().to_owned(),
+            temp_dir.path().join(".zk_index"),
+        );
         let watcher = super::DirWatcher::new(
-            temp_dir.path().to_str().unwrap(),
+            &config,
             index_event_sender,
             Box::new(file_has_no_hidden_component),
         );
@@ -1,5 +1,8 @@
 //! An extension for markdown-it that parses wikilinks.
 //! A wikilink is a link that looks like this: `[[link]]`.
+//! It also supports an anchor into a heading or block (`[[link#heading]]`,
+//! `[[link#^blockid]]`) and a display alias after a pipe (`[[link|alias]]`),
+//! which may be combined as `[[link#heading|alias]]`.
 
 use markdown_it::{
     parser::inline::{InlineRule, InlineState},
@@ -11,11 +14,13 @@ use regex::Regex;
 #[derive(Debug, Clone)]
 pub struct Wikilink {
     pub target: String,
+    pub anchor: Option<String>,
+    pub display: Option<String>,
 }
 
 impl NodeValue for Wikilink {
     fn render(&self, _: &Node, fmt: &mut dyn Renderer) {
-        fmt.text_raw(&self.target);
+        fmt.text_raw(self.display.as_deref().unwrap_or(&self.target));
     }
 }
 
@@ -27,6 +32,25 @@ pub fn add(md: &mut MarkdownIt) {
 pub static WIKILINK_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^\[\[[^\]]+\]\]").expect("Failed to compile WIKILINK_RE regex."));
 
+/// Split a wikilink's inner `target#anchor|display` text into its parts.
+/// The alias is split off first, so a `#` that happens to appear in the
+/// display text isn't mistaken for an anchor.
+fn parse_wikilink_inner(inner: &str) -> Wikilink {
+    let (before_alias, display) = match inner.split_once('|') {
+        Some((before, alias)) => (before, Some(alias.to_string())),
+        None => (inner, None),
+    };
+    let (target, anchor) = match before_alias.split_once('#') {
+        Some((target, anchor)) => (target.to_string(), Some(anchor.to_string())),
+        None => (before_alias.to_string(), None),
+    };
+    Wikilink {
+        target,
+        anchor,
+        display,
+    }
+}
+
 #[doc(hidden)]
 pub struct WikilinkScanner;
 impl InlineRule for WikilinkScanner {
@@ -39,15 +63,90 @@ impl InlineRule for WikilinkScanner {
 
         match capture {
             Some(capture) => {
-                // The capture includes the brackets, so we need to remove them.
-                let target = &capture[2..capture.len() - 2];
-
-                let node = Node::new(Wikilink {
-                    target: target.to_string(),
-                });
-                Some((node, target.len()))
+                // The capture includes the brackets, so we need to remove them
+                // to parse the inner `target#anchor|display` text, but the
+                // consumed length returned below must still be the whole
+                // match (brackets included), or the inline parser's cursor
+                // falls 4 bytes short of the closing `]]` and every node's
+                // `srcmap` after this wikilink drifts by that much.
+                let inner = &capture[2..capture.len() - 2];
+                let node = Node::new(parse_wikilink_inner(inner));
+                Some((node, capture.len()))
             }
             None => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wikilink_inner_bare() {
+        let wikilink = parse_wikilink_inner("note");
+        assert_eq!(wikilink.target, "note");
+        assert_eq!(wikilink.anchor, None);
+        assert_eq!(wikilink.display, None);
+    }
+
+    #[test]
+    fn test_parse_wikilink_inner_alias() {
+        let wikilink = parse_wikilink_inner("note|display text");
+        assert_eq!(wikilink.target, "note");
+        assert_eq!(wikilink.anchor, None);
+        assert_eq!(wikilink.display, Some("display text".to_string()));
+    }
+
+    #[test]
+    fn test_parse_wikilink_inner_anchor() {
+        let wikilink = parse_wikilink_inner("note#heading");
+        assert_eq!(wikilink.target, "note");
+        assert_eq!(wikilink.anchor, Some("heading".to_string()));
+        assert_eq!(wikilink.display, None);
+    }
+
+    #[test]
+    fn test_parse_wikilink_inner_anchor_and_alias() {
+        let wikilink = parse_wikilink_inner("note#^blockid|display text");
+        assert_eq!(wikilink.target, "note");
+        assert_eq!(wikilink.anchor, Some("^blockid".to_string()));
+        assert_eq!(wikilink.display, Some("display text".to_string()));
+    }
+
+    #[test]
+    fn test_parse_wikilink_inner_hash_in_display_is_not_an_anchor() {
+        // The alias is split off before the anchor, so a `#` that happens to
+        // appear in the display text isn't mistaken for an anchor separator.
+        let wikilink = parse_wikilink_inner("note|see #tag for more");
+        assert_eq!(wikilink.target, "note");
+        assert_eq!(wikilink.anchor, None);
+        assert_eq!(wikilink.display, Some("see #tag for more".to_string()));
+    }
+
+    fn parse(src: &str) -> Node {
+        let mut md = markdown_it::MarkdownIt::new();
+        markdown_it::plugins::cmark::add(&mut md);
+        add(&mut md);
+        md.parse(src)
+    }
+
+    #[test]
+    fn test_srcmap_covers_the_whole_bracketed_match() {
+        // Regression test for the scanner returning `inner.len()` (4 bytes
+        // short of the full `[[...]]` match) as the consumed length, which
+        // left every srcmap offset after a wikilink pointing 4 bytes too
+        // early.
+        let tree = parse("[[note]] and then some trailing text");
+        let mut found = false;
+        tree.walk(|node, _| {
+            if node.is::<Wikilink>() {
+                let (start, end) = node.srcmap.unwrap().get_byte_offsets();
+                assert_eq!(start, 0);
+                assert_eq!(end, "[[note]]".len());
+                found = true;
+            }
+        });
+        assert!(found, "expected to find a Wikilink node");
+    }
+}